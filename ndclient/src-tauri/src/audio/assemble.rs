@@ -0,0 +1,344 @@
+use std::{path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{Audio, AudioError, AudioResult, AudioSpec};
+
+/// How the TTS clips are joined into the dubbing
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssemblyConfig {
+    /// sample rate of the dubbing, `0` takes the one of the first clip
+    pub sample_rate: u32,
+    /// channels of the dubbing, `0` takes the one of the first clip
+    pub channels: u16,
+    /// silence after a clip whose sentence ends without the punctuation below
+    pub pause_ms: u64,
+    /// silence after a clause, ending with `，、；：`
+    pub short_pause_ms: u64,
+    /// silence after a sentence, ending with `。！？…`
+    pub long_pause_ms: u64,
+    /// cut the silence at both ends of every clip
+    pub trim_silence: bool,
+    /// quieter than this is silence, in dBFS
+    pub silence_db: f64,
+    /// silence kept at both ends of a trimmed clip
+    pub trim_padding_ms: u64,
+}
+
+impl Default for AssemblyConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0,
+            channels: 0,
+            pause_ms: 300,
+            short_pause_ms: 150,
+            long_pause_ms: 450,
+            trim_silence: true,
+            silence_db: -45.0,
+            trim_padding_ms: 30,
+        }
+    }
+}
+
+impl AssemblyConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sample_rate != 0 && !(8000..=192_000).contains(&self.sample_rate) {
+            return Err(format!(
+                "sample_rate must be 0 or in 8000 ~ 192000, got {}",
+                self.sample_rate
+            ));
+        }
+        if self.channels > 8 {
+            return Err(format!("channels must be in 0 ~ 8, got {}", self.channels));
+        }
+        for (name, pause) in [
+            ("pause_ms", self.pause_ms),
+            ("short_pause_ms", self.short_pause_ms),
+            ("long_pause_ms", self.long_pause_ms),
+        ] {
+            if pause > 10_000 {
+                return Err(format!("{name} must be at most 10000, got {pause}"));
+            }
+        }
+        if !(-90.0..=-10.0).contains(&self.silence_db) {
+            return Err(format!(
+                "silence_db must be in -90 ~ -10, got {}",
+                self.silence_db
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Silence after the sentence `text`, by its last punctuation
+    pub fn pause_after(&self, text: &str) -> Duration {
+        // closing quotes and brackets do not end anything
+        let last = text
+            .trim_end()
+            .trim_end_matches(['”', '’', '"', '\'', '」', '』', '）', ')', '》'])
+            .chars()
+            .next_back();
+
+        let ms = match last {
+            Some('。' | '！' | '？' | '…' | '.' | '!' | '?') => self.long_pause_ms,
+            Some('，' | '、' | '；' | '：' | ',' | ';' | ':') => self.short_pause_ms,
+            _ => self.pause_ms,
+        };
+
+        Duration::from_millis(ms)
+    }
+}
+
+/// Where a clip is in the assembled audio
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClipOffset {
+    pub start: Duration,
+    pub duration: Duration,
+    /// leading silence cut from the clip, timings inside the clip move back by it
+    pub trimmed: Duration,
+}
+
+impl ClipOffset {
+    pub fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+}
+
+pub struct Assembled {
+    pub audio: Audio,
+    /// one per clip, in order
+    pub offsets: Vec<ClipOffset>,
+}
+
+/// Joins clips of any WAV format into one audio of a single spec
+pub struct Assembler {
+    config: AssemblyConfig,
+}
+
+impl Assembler {
+    pub fn new(config: AssemblyConfig) -> Self {
+        Self { config }
+    }
+
+    /// Assemble the clips, each with the text it speaks
+    pub fn assemble(&self, clips: &[(impl AsRef<Path>, &str)]) -> AudioResult<Assembled> {
+        let clips = clips
+            .iter()
+            .map(|(path, text)| Ok((Audio::read(path)?, *text)))
+            .collect::<AudioResult<Vec<_>>>()?;
+
+        self.assemble_audio(clips)
+    }
+
+    pub fn assemble_audio(&self, clips: Vec<(Audio, &str)>) -> AudioResult<Assembled> {
+        let first = clips.first().ok_or(AudioError::Empty)?.0.spec();
+        let spec = AudioSpec {
+            sample_rate: match self.config.sample_rate {
+                0 => first.sample_rate,
+                rate => rate,
+            },
+            channels: match self.config.channels {
+                0 => first.channels,
+                channels => channels,
+            },
+        };
+
+        let frames_of = |time: Duration| (time.as_secs_f64() * spec.sample_rate as f64) as usize;
+        // times from frame counts, so the offsets add up exactly
+        let time = |frames: usize| {
+            Duration::from_nanos(frames as u64 * 1_000_000_000 / spec.sample_rate as u64)
+        };
+        let padding = Duration::from_millis(self.config.trim_padding_ms);
+
+        let mut samples = vec![];
+        let mut offsets = Vec::with_capacity(clips.len());
+        let mut frames = 0;
+
+        for (clip, text) in clips {
+            let clip = clip.convert(spec);
+            let (clip, trimmed) = if self.config.trim_silence {
+                clip.trim_silence(self.config.silence_db, padding)
+            } else {
+                (clip, Duration::ZERO)
+            };
+            let clip_frames = clip.frames();
+            let pause_frames = frames_of(self.config.pause_after(text));
+
+            offsets.push(ClipOffset {
+                start: time(frames),
+                duration: time(clip_frames),
+                trimmed,
+            });
+
+            samples.extend(clip.samples);
+            samples.resize(samples.len() + pause_frames * spec.channels as usize, 0.0);
+            frames += clip_frames + pause_frames;
+        }
+
+        Ok(Assembled {
+            audio: Audio {
+                sample_rate: spec.sample_rate,
+                channels: spec.channels,
+                samples,
+            },
+            offsets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use super::*;
+    use crate::workspace::{DEFAULT_TEMP_DIR, Workspace};
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn mixed_formats() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+
+        // 24 bit stereo at 48 kHz, 100 ms
+        let deep = workspace.path("deep.wav");
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 48_000,
+            bits_per_sample: 24,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&deep, spec).unwrap();
+        for _ in 0..4800 {
+            writer.write_sample(1 << 22).unwrap();
+            writer.write_sample(1 << 22).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        // float mono at 24 kHz, 200 ms
+        let float = workspace.path("float.wav");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 24_000,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&float, spec).unwrap();
+        for _ in 0..4800 {
+            writer.write_sample(-0.25f32).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let assembled = Assembler::new(AssemblyConfig {
+            sample_rate: 16_000,
+            channels: 1,
+            pause_ms: 50,
+            ..AssemblyConfig::default()
+        })
+        .assemble(&[(deep, "一"), (float, "二")])
+        .unwrap();
+
+        assert_eq!(
+            assembled.offsets,
+            [
+                ClipOffset {
+                    start: millis(0),
+                    duration: millis(100),
+                    trimmed: Duration::ZERO,
+                },
+                ClipOffset {
+                    start: millis(150),
+                    duration: millis(200),
+                    trimmed: Duration::ZERO,
+                },
+            ]
+        );
+        let audio = &assembled.audio;
+        assert_eq!(
+            audio.spec(),
+            AudioSpec {
+                sample_rate: 16_000,
+                channels: 1
+            }
+        );
+        assert_eq!(audio.duration(), millis(400));
+        assert!((audio.samples[800] - 0.5).abs() < 1e-3);
+        assert_eq!(audio.samples[1700], 0.0);
+        assert!((audio.samples[3000] + 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn spec_of_the_first_clip() {
+        let clips = vec![
+            (Audio::from_channels(8000, vec![vec![0.1; 80]]), ""),
+            (
+                Audio::from_channels(16000, vec![vec![0.2; 160], vec![0.2; 160]]),
+                "",
+            ),
+        ];
+
+        let assembled = Assembler::new(AssemblyConfig::default())
+            .assemble_audio(clips)
+            .unwrap();
+
+        assert_eq!(
+            assembled.audio.spec(),
+            AudioSpec {
+                sample_rate: 8000,
+                channels: 1
+            }
+        );
+        assert_eq!(assembled.offsets[1].start, millis(310));
+        assert_eq!(assembled.offsets[1].end(), millis(320));
+    }
+
+    #[test]
+    fn trim_and_pause_by_punctuation() {
+        // 200 ms of silence on both sides of 100 ms of voice
+        let clip = || {
+            let mut samples = vec![0.0; 1600];
+            samples.extend(vec![0.5; 800]);
+            samples.extend(vec![0.0; 1600]);
+            Audio::from_channels(8000, vec![samples])
+        };
+
+        let assembled = Assembler::new(AssemblyConfig::default())
+            .assemble_audio(vec![
+                (clip(), "你好，"),
+                (clip(), "“结束。”"),
+                (clip(), "没了"),
+            ])
+            .unwrap();
+
+        let offsets = &assembled.offsets;
+        assert_eq!(offsets[0].duration, millis(160));
+        assert_eq!(offsets[0].trimmed, millis(170));
+        assert_eq!(offsets[1].start, millis(160 + 150));
+        assert_eq!(offsets[2].start, millis(2 * 160 + 150 + 450));
+        assert_eq!(
+            assembled.audio.duration(),
+            millis(3 * 160 + 150 + 450 + 300)
+        );
+    }
+
+    #[test]
+    fn keep_silent_clip() {
+        let silence = Audio::from_channels(8000, vec![vec![0.0; 800]]);
+
+        let assembled = Assembler::new(AssemblyConfig::default())
+            .assemble_audio(vec![(silence, "")])
+            .unwrap();
+
+        assert_eq!(assembled.offsets[0].duration, millis(100));
+        assert_eq!(assembled.offsets[0].trimmed, Duration::ZERO);
+    }
+
+    #[test]
+    fn nothing_to_assemble() {
+        let result = Assembler::new(AssemblyConfig::default()).assemble_audio(vec![]);
+
+        assert!(matches!(result, Err(AudioError::Empty)));
+    }
+}
@@ -0,0 +1,439 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{Audio, resample};
+
+/// A voice effect, see [`EffectChain`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Effect {
+    /// faster and higher together, like a tape played faster
+    Speed { rate: f32 },
+    /// faster or slower, the pitch is kept
+    Tempo { rate: f32 },
+    /// higher or lower, the duration is kept
+    Pitch { semitones: f32 },
+    /// tone colour
+    Eq { preset: EqPreset },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EqPreset {
+    /// band limited with a presence boost
+    Radio,
+    /// narrow band
+    Telephone,
+    /// more treble
+    Bright,
+    /// more bass, less treble
+    Warm,
+}
+
+/// Effects applied in order on every TTS clip, empty for the original voice
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EffectChain(pub Vec<Effect>);
+
+/// A named chain, for picking in the app
+#[derive(Clone, Debug, Serialize)]
+pub struct EffectPreset {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub chain: EffectChain,
+}
+
+impl EffectChain {
+    /// The chipmunk voice the videos always had
+    pub fn carton() -> Self {
+        Self(vec![Effect::Speed { rate: 1.25 }])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for effect in &self.0 {
+            match *effect {
+                Effect::Speed { rate } | Effect::Tempo { rate } if !(0.5..=2.0).contains(&rate) => {
+                    return Err(format!("rate must be in 0.5 ~ 2.0, got {rate}"));
+                }
+                Effect::Pitch { semitones } if !(-12.0..=12.0).contains(&semitones) => {
+                    return Err(format!("semitones must be in -12 ~ 12, got {semitones}"));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn apply(&self, audio: Audio) -> Audio {
+        if self.is_empty() {
+            return audio;
+        }
+
+        let sample_rate = audio.sample_rate;
+        let channels = audio
+            .split_channels()
+            .into_iter()
+            .map(|mut channel| {
+                for effect in &self.0 {
+                    channel = apply_effect(effect, &channel, sample_rate);
+                }
+                channel
+            })
+            .collect();
+
+        Audio::from_channels(sample_rate, channels)
+    }
+}
+
+/// The chains offered in the app
+pub fn presets() -> Vec<EffectPreset> {
+    vec![
+        EffectPreset {
+            id: "none",
+            name: "原声",
+            chain: EffectChain::default(),
+        },
+        EffectPreset {
+            id: "carton",
+            name: "卡通",
+            chain: EffectChain::carton(),
+        },
+        EffectPreset {
+            id: "deep",
+            name: "低沉",
+            chain: EffectChain(vec![Effect::Pitch { semitones: -4.0 }]),
+        },
+        EffectPreset {
+            id: "radio",
+            name: "电台",
+            chain: EffectChain(vec![Effect::Eq {
+                preset: EqPreset::Radio,
+            }]),
+        },
+        EffectPreset {
+            id: "fast",
+            name: "快语速",
+            chain: EffectChain(vec![Effect::Tempo { rate: 1.2 }]),
+        },
+    ]
+}
+
+fn apply_effect(effect: &Effect, samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    match *effect {
+        Effect::Speed { rate } => resample(samples, rate as f64),
+        Effect::Tempo { rate } => stretch(samples, rate as f64, sample_rate),
+        Effect::Pitch { semitones } => {
+            let ratio = 2f64.powf(semitones as f64 / 12.0);
+            // longer by the ratio, then played faster by it
+            resample(&stretch(samples, 1.0 / ratio, sample_rate), ratio)
+        }
+        Effect::Eq { preset } => {
+            let mut samples = samples.to_vec();
+            for band in preset.bands() {
+                if let Some(filter) = Biquad::new(band, sample_rate) {
+                    filter.process(&mut samples);
+                }
+            }
+            samples
+        }
+    }
+}
+
+/// Analysis frame of [`stretch`]
+const FRAME_MS: u32 = 40;
+/// How far a frame may move to match the output, in [`stretch`]
+const TOLERANCE_MS: u32 = 10;
+
+/// Change the tempo by `tempo` keeping the pitch, by WSOLA:
+/// windowed frames are taken from the input at the tempo and overlapped in the output,
+/// each moved a bit to where it continues the output most smoothly.
+fn stretch(samples: &[f32], tempo: f64, sample_rate: u32) -> Vec<f32> {
+    let frame = (sample_rate * FRAME_MS / 1000).max(64) as usize;
+    if tempo == 1.0 || samples.len() <= frame {
+        return resample(samples, tempo);
+    }
+
+    let hop_out = frame / 2;
+    let hop_in = hop_out as f64 * tempo;
+    let tolerance = (sample_rate * TOLERANCE_MS / 1000) as usize;
+    let last_start = samples.len() - frame;
+    let out_len = (samples.len() as f64 / tempo) as usize;
+
+    let window: Vec<f32> = (0..frame)
+        .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f64 / frame as f64).cos()) as f32)
+        .collect();
+
+    let mut out = vec![0.0f32; out_len + frame];
+    let mut weight = vec![0.0f32; out_len + frame];
+    let mut prev: Option<usize> = None;
+
+    for k in 0.. {
+        let out_pos = k * hop_out;
+        if out_pos >= out_len {
+            break;
+        }
+
+        let nominal = ((k as f64 * hop_in) as usize).min(last_start);
+        let start = match prev {
+            None => nominal,
+            Some(prev) => {
+                // what would naturally follow the previous frame
+                let natural = (prev + hop_out).min(last_start);
+                let from = nominal.saturating_sub(tolerance);
+                let to = (nominal + tolerance).min(last_start);
+
+                let score = |candidate: usize| {
+                    (0..hop_out)
+                        .step_by(2)
+                        .map(|i| samples[candidate + i] * samples[natural + i])
+                        .sum::<f32>()
+                };
+
+                (from..=to)
+                    .map(|candidate| (candidate, score(candidate)))
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(candidate, _)| candidate)
+                    .unwrap_or(nominal)
+            }
+        };
+
+        for i in 0..frame {
+            out[out_pos + i] += samples[start + i] * window[i];
+            weight[out_pos + i] += window[i];
+        }
+        prev = Some(start);
+    }
+
+    out.truncate(out_len);
+    for (sample, weight) in out.iter_mut().zip(weight) {
+        if weight > 1e-3 {
+            *sample /= weight;
+        }
+    }
+
+    out
+}
+
+/// A band of an EQ preset
+enum Band {
+    HighPass(f64),
+    LowPass(f64),
+    Peak { freq: f64, gain_db: f64 },
+    LowShelf { freq: f64, gain_db: f64 },
+    HighShelf { freq: f64, gain_db: f64 },
+}
+
+impl EqPreset {
+    fn bands(self) -> Vec<Band> {
+        match self {
+            Self::Radio => vec![
+                Band::HighPass(300.0),
+                Band::LowPass(4000.0),
+                Band::Peak {
+                    freq: 1500.0,
+                    gain_db: 4.0,
+                },
+            ],
+            Self::Telephone => vec![Band::HighPass(500.0), Band::LowPass(3000.0)],
+            Self::Bright => vec![Band::HighShelf {
+                freq: 4000.0,
+                gain_db: 5.0,
+            }],
+            Self::Warm => vec![
+                Band::LowShelf {
+                    freq: 250.0,
+                    gain_db: 4.0,
+                },
+                Band::HighShelf {
+                    freq: 6000.0,
+                    gain_db: -4.0,
+                },
+            ],
+        }
+    }
+}
+
+/// Second order filter of the Audio EQ Cookbook by Robert Bristow-Johnson
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    /// `None` if the band is above what the sample rate can hold
+    fn new(band: Band, sample_rate: u32) -> Option<Self> {
+        let freq = match band {
+            Band::HighPass(freq) | Band::LowPass(freq) => freq,
+            Band::Peak { freq, .. }
+            | Band::LowShelf { freq, .. }
+            | Band::HighShelf { freq, .. } => freq,
+        };
+        if freq >= sample_rate as f64 * 0.45 {
+            return None;
+        }
+
+        let w0 = 2.0 * PI * freq / sample_rate as f64;
+        let (sin, cos) = w0.sin_cos();
+        let q = std::f64::consts::FRAC_1_SQRT_2;
+        let alpha = sin / (2.0 * q);
+
+        let (b, a) = match band {
+            Band::HighPass(_) => (
+                [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+            Band::LowPass(_) => (
+                [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+            Band::Peak { gain_db, .. } => {
+                let amp = 10f64.powf(gain_db / 40.0);
+                (
+                    [1.0 + alpha * amp, -2.0 * cos, 1.0 - alpha * amp],
+                    [1.0 + alpha / amp, -2.0 * cos, 1.0 - alpha / amp],
+                )
+            }
+            Band::LowShelf { gain_db, .. } => {
+                let amp = 10f64.powf(gain_db / 40.0);
+                let k = 2.0 * amp.sqrt() * alpha;
+                (
+                    [
+                        amp * ((amp + 1.0) - (amp - 1.0) * cos + k),
+                        2.0 * amp * ((amp - 1.0) - (amp + 1.0) * cos),
+                        amp * ((amp + 1.0) - (amp - 1.0) * cos - k),
+                    ],
+                    [
+                        (amp + 1.0) + (amp - 1.0) * cos + k,
+                        -2.0 * ((amp - 1.0) + (amp + 1.0) * cos),
+                        (amp + 1.0) + (amp - 1.0) * cos - k,
+                    ],
+                )
+            }
+            Band::HighShelf { gain_db, .. } => {
+                let amp = 10f64.powf(gain_db / 40.0);
+                let k = 2.0 * amp.sqrt() * alpha;
+                (
+                    [
+                        amp * ((amp + 1.0) + (amp - 1.0) * cos + k),
+                        -2.0 * amp * ((amp - 1.0) + (amp + 1.0) * cos),
+                        amp * ((amp + 1.0) + (amp - 1.0) * cos - k),
+                    ],
+                    [
+                        (amp + 1.0) - (amp - 1.0) * cos + k,
+                        2.0 * ((amp - 1.0) - (amp + 1.0) * cos),
+                        (amp + 1.0) - (amp - 1.0) * cos - k,
+                    ],
+                )
+            }
+        };
+
+        Some(Self {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+        })
+    }
+
+    fn process(&self, samples: &mut [f32]) {
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        for sample in samples {
+            let x = *sample as f64;
+            let y =
+                self.b[0] * x + self.b[1] * x1 + self.b[2] * x2 - self.a[0] * y1 - self.a[1] * y2;
+            (x2, x1, y2, y1) = (x1, x, y1, y);
+            *sample = y as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const RATE: u32 = 16_000;
+
+    fn sine(hz: f64, millis: u32) -> Audio {
+        let samples = (0..RATE * millis / 1000)
+            .map(|i| ((2.0 * PI * hz * i as f64 / RATE as f64).sin() * 0.5) as f32)
+            .collect();
+        Audio::from_channels(RATE, vec![samples])
+    }
+
+    /// Frequency by the zero crossings, away from the edges
+    fn frequency(audio: &Audio) -> f64 {
+        let samples = &audio.samples[audio.samples.len() / 4..audio.samples.len() * 3 / 4];
+        let crossings = samples
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count();
+        crossings as f64 / 2.0 / (samples.len() as f64 / RATE as f64)
+    }
+
+    fn rms(audio: &Audio) -> f32 {
+        (audio.samples.iter().map(|s| s * s).sum::<f32>() / audio.samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn speed_changes_both() {
+        let out = EffectChain::carton().apply(sine(400.0, 1000));
+
+        assert_eq!(out.duration(), Duration::from_millis(800));
+        assert!((frequency(&out) - 500.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn tempo_keeps_pitch() {
+        let out = EffectChain(vec![Effect::Tempo { rate: 1.25 }]).apply(sine(400.0, 1000));
+
+        assert_eq!(out.duration(), Duration::from_millis(800));
+        assert!((frequency(&out) - 400.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn pitch_keeps_duration() {
+        let out = EffectChain(vec![Effect::Pitch { semitones: 12.0 }]).apply(sine(300.0, 1000));
+
+        assert!(out.duration().abs_diff(Duration::from_secs(1)) < Duration::from_millis(5));
+        assert!((frequency(&out) - 600.0).abs() < 15.0);
+    }
+
+    #[test]
+    fn radio_cuts_bass() {
+        let radio = EffectChain(vec![Effect::Eq {
+            preset: EqPreset::Radio,
+        }]);
+
+        assert!(rms(&radio.apply(sine(80.0, 500))) < 0.1 * rms(&sine(80.0, 500)));
+        assert!(rms(&radio.apply(sine(1500.0, 500))) > 0.9 * rms(&sine(1500.0, 500)));
+    }
+
+    #[test]
+    fn none_is_untouched() {
+        let audio = sine(400.0, 100);
+
+        assert_eq!(EffectChain::default().apply(audio.clone()), audio);
+    }
+
+    #[test]
+    fn parse_and_validate() {
+        let chain: EffectChain = serde_json::from_str(
+            r#"[{"type": "pitch", "semitones": -4}, {"type": "eq", "preset": "warm"}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(chain.0.len(), 2);
+        assert!(chain.validate().is_ok());
+        assert!(
+            EffectChain(vec![Effect::Tempo { rate: 3.0 }])
+                .validate()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::{cmp::Reverse, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// Length of the video to reach, e.g. the cap of a platform
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LengthConfig {
+    /// in seconds, `0` keeps the natural length
+    pub target_secs: f64,
+    /// slowest tempo of the speech, below 1 is slower
+    pub min_tempo: f64,
+    /// fastest tempo of the speech, sentences are dropped beyond it
+    pub max_tempo: f64,
+}
+
+impl Default for LengthConfig {
+    fn default() -> Self {
+        Self {
+            target_secs: 0.0,
+            min_tempo: 0.9,
+            max_tempo: 1.2,
+        }
+    }
+}
+
+impl LengthConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=600.0).contains(&self.target_secs) {
+            return Err(format!(
+                "target_secs must be in 0 ~ 600, got {}",
+                self.target_secs
+            ));
+        }
+        if !(0.5..=1.0).contains(&self.min_tempo) {
+            return Err(format!(
+                "min_tempo must be in 0.5 ~ 1, got {}",
+                self.min_tempo
+            ));
+        }
+        if !(1.0..=2.0).contains(&self.max_tempo) {
+            return Err(format!(
+                "max_tempo must be in 1 ~ 2, got {}",
+                self.max_tempo
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn target(&self) -> Option<Duration> {
+        (self.target_secs > 0.0).then(|| Duration::from_secs_f64(self.target_secs))
+    }
+}
+
+/// A sentence of the dubbing as it was assembled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FitClip {
+    pub duration: Duration,
+    /// the silence after it, not stretched
+    pub pause: Duration,
+    /// see [`crate::news::Sentence::priority`]
+    pub priority: u8,
+    pub emphasis: bool,
+}
+
+/// How the dubbing reaches the target length
+#[derive(Clone, Debug, PartialEq)]
+pub struct LengthFit {
+    /// of every kept clip, above 1 is faster
+    pub tempo: f64,
+    /// indexes of the clips left out, in order
+    pub dropped: Vec<usize>,
+}
+
+/// How the video was fit to the target length, for the job metadata
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LengthReport {
+    /// length of the dubbing before the fitting
+    pub natural_secs: f64,
+    /// after the fitting, then padded with silence or cut to the target
+    pub fitted_secs: f64,
+    pub target_secs: f64,
+    pub tempo: f64,
+    /// the sentences left out
+    pub dropped: Vec<String>,
+}
+
+/// Tempo changes smaller than this are not worth the stretching
+const MIN_TEMPO_CHANGE: f64 = 0.01;
+
+/// Plan the tempo, and the sentences to drop when even the fastest tempo is too long.
+///
+/// The lowest priority goes first, emphasised sentences last, and from the end on
+/// at the same priority. The first sentence, the hook of the video, is always kept,
+/// so the fitted length is still over the target when it is too long on its own.
+/// The rest of the length, when the slowest tempo is still too short, is silence.
+pub fn plan(config: &LengthConfig, target: Duration, clips: &[FitClip]) -> LengthFit {
+    let mut kept: Vec<usize> = (0..clips.len()).collect();
+
+    let tempo_of = |kept: &[usize]| {
+        let speech: Duration = kept.iter().map(|&i| clips[i].duration).sum();
+        let pauses: Duration = kept.iter().map(|&i| clips[i].pause).sum();
+        match target.checked_sub(pauses) {
+            Some(room) if !room.is_zero() => speech.as_secs_f64() / room.as_secs_f64(),
+            _ => f64::INFINITY,
+        }
+    };
+
+    while tempo_of(&kept) > config.max_tempo && kept.len() > 1 {
+        let drop = kept[1..]
+            .iter()
+            .copied()
+            .min_by_key(|&i| (clips[i].emphasis, clips[i].priority, Reverse(i)))
+            .unwrap();
+        kept.retain(|&i| i != drop);
+    }
+
+    let tempo = tempo_of(&kept).clamp(config.min_tempo, config.max_tempo);
+    let tempo = if (tempo - 1.0).abs() < MIN_TEMPO_CHANGE {
+        1.0
+    } else {
+        tempo
+    };
+
+    LengthFit {
+        tempo,
+        dropped: (0..clips.len()).filter(|i| !kept.contains(i)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(secs: u64, priority: u8) -> FitClip {
+        FitClip {
+            duration: Duration::from_secs(secs),
+            pause: Duration::from_millis(500),
+            priority,
+            emphasis: false,
+        }
+    }
+
+    fn target(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn faster_within_range() {
+        // 20s of speech and 2s of pauses in 20s
+        let clips = [clip(5, 2), clip(5, 2), clip(5, 2), clip(5, 2)];
+
+        let fit = plan(&LengthConfig::default(), target(20), &clips);
+
+        assert!((fit.tempo - 20.0 / 18.0).abs() < 1e-9);
+        assert!(fit.dropped.is_empty());
+    }
+
+    #[test]
+    fn drop_low_priority() {
+        let mut clips = [clip(5, 1), clip(5, 3), clip(5, 1), clip(5, 2), clip(5, 1)];
+        clips[4].emphasis = true;
+
+        let fit = plan(&LengthConfig::default(), target(15), &clips);
+
+        // the first is the hook and the last is emphasised, the third goes first
+        assert_eq!(fit.dropped, [2, 3]);
+        assert!((fit.tempo - 15.0 / 13.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn slower_and_pad() {
+        let clips = [clip(4, 2), clip(4, 2)];
+
+        let fit = plan(&LengthConfig::default(), target(60), &clips);
+
+        assert_eq!(fit.tempo, 0.9);
+        assert!(fit.dropped.is_empty());
+    }
+
+    #[test]
+    fn about_right() {
+        let clips = [clip(9, 2)];
+
+        let fit = plan(
+            &LengthConfig::default(),
+            Duration::from_millis(9550),
+            &clips,
+        );
+
+        assert_eq!(fit.tempo, 1.0);
+    }
+
+    #[test]
+    fn keep_the_hook() {
+        let clips = [clip(30, 1), clip(5, 3)];
+
+        let fit = plan(&LengthConfig::default(), target(10), &clips);
+
+        assert_eq!(fit.dropped, [1]);
+        assert_eq!(fit.tempo, 1.2);
+    }
+}
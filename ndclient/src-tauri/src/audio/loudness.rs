@@ -0,0 +1,395 @@
+use std::{collections::VecDeque, f64::consts::PI};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::Audio;
+
+/// Loudness normalisation of the dubbing, EBU R128 / ITU-R BS.1770
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoudnessConfig {
+    pub enabled: bool,
+    /// integrated loudness to reach, in LUFS, -23 for broadcast, about -14 ~ -16 for short videos
+    pub target_lufs: f64,
+    /// maximum true peak, in dBTP
+    pub true_peak_db: f64,
+}
+
+impl Default for LoudnessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target_lufs: -16.0,
+            true_peak_db: -1.0,
+        }
+    }
+}
+
+impl LoudnessConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(-40.0..=-5.0).contains(&self.target_lufs) {
+            return Err(format!(
+                "target_lufs must be in -40 ~ -5, got {}",
+                self.target_lufs
+            ));
+        }
+        if !(-9.0..=0.0).contains(&self.true_peak_db) {
+            return Err(format!(
+                "true_peak_db must be in -9 ~ 0, got {}",
+                self.true_peak_db
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Loudness before and after the normalisation.
+/// `None` loudness means the audio is silent or shorter than a 400 ms block.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LoudnessReport {
+    pub input_lufs: Option<f64>,
+    pub input_true_peak_db: f64,
+    pub output_lufs: Option<f64>,
+    pub output_true_peak_db: f64,
+    /// gain applied before the limiter
+    pub gain_db: f64,
+}
+
+/// Bring `audio` to the target loudness, then limit its true peak
+pub fn normalize(audio: &mut Audio, config: &LoudnessConfig) -> LoudnessReport {
+    let input_lufs = integrated_loudness(audio);
+    let input_true_peak_db = true_peak_db(audio);
+
+    let gain_db = input_lufs.map_or(0.0, |lufs| config.target_lufs - lufs);
+    let gain = db_to_gain(gain_db) as f32;
+    audio.samples.iter_mut().for_each(|s| *s *= gain);
+
+    limit(audio, db_to_gain(config.true_peak_db) as f32);
+
+    LoudnessReport {
+        input_lufs,
+        input_true_peak_db,
+        output_lufs: integrated_loudness(audio),
+        output_true_peak_db: true_peak_db(audio),
+        gain_db,
+    }
+}
+
+pub(crate) fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// Length of a gating block
+const BLOCK_MS: u64 = 400;
+/// Blocks overlap by 75%
+const STEP_MS: u64 = 100;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Gated integrated loudness in LUFS
+pub fn integrated_loudness(audio: &Audio) -> Option<f64> {
+    let rate = audio.sample_rate as u64;
+    let block = (rate * BLOCK_MS / 1000) as usize;
+    let step = (rate * STEP_MS / 1000) as usize;
+    let channels = audio.split_channels();
+    let frames = audio.frames();
+    if frames < block || block == 0 {
+        return None;
+    }
+
+    // the surround channels of 5.1 weigh more, LFE not at all
+    let weights: Vec<f64> = if channels.len() == 6 {
+        vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41]
+    } else {
+        vec![1.0; channels.len()]
+    };
+
+    // squares of the K-weighted samples, summed over the weighted channels
+    let mut power = vec![0.0f64; frames];
+    for (channel, weight) in channels.iter().zip(&weights) {
+        if *weight == 0.0 {
+            continue;
+        }
+        for (p, s) in power.iter_mut().zip(k_weighted(channel, audio.sample_rate)) {
+            *p += weight * s * s;
+        }
+    }
+
+    let mut sums = vec![0.0f64; frames + 1];
+    for (i, p) in power.iter().enumerate() {
+        sums[i + 1] = sums[i] + p;
+    }
+    let blocks: Vec<f64> = (0..=(frames - block) / step)
+        .map(|k| (sums[k * step + block] - sums[k * step]) / block as f64)
+        .collect();
+
+    let loudness = |energy: f64| -0.691 + 10.0 * energy.log10();
+    let mean_above = |gate: f64| {
+        let gated: Vec<f64> = blocks
+            .iter()
+            .copied()
+            .filter(|&z| z > 0.0 && loudness(z) > gate)
+            .collect();
+        (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+    };
+
+    let relative = loudness(mean_above(ABSOLUTE_GATE)?) + RELATIVE_GATE;
+    mean_above(relative.max(ABSOLUTE_GATE)).map(loudness)
+}
+
+/// The K-weighting of BS.1770: a high shelf for the head, then a high pass
+fn k_weighted(samples: &[f32], sample_rate: u32) -> Vec<f64> {
+    let rate = sample_rate as f64;
+
+    // shelf
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = db_to_gain(gain_db);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = (
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    // high pass
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = (
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let filter = |input: Vec<f64>, (b, a): ([f64; 3], [f64; 2])| {
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        input
+            .into_iter()
+            .map(|x| {
+                let y = b[0] * x + b[1] * x1 + b[2] * x2 - a[0] * y1 - a[1] * y2;
+                (x2, x1, y2, y1) = (x1, x, y1, y);
+                y
+            })
+            .collect::<Vec<f64>>()
+    };
+
+    let samples = samples.iter().map(|&s| s as f64).collect();
+    filter(filter(samples, shelf), high_pass)
+}
+
+/// Oversampling of the true peak measurement
+const OVERSAMPLE: usize = 4;
+/// Half length of the interpolation filter
+const TAPS: isize = 6;
+
+/// The peak between the samples too, by 4x windowed sinc interpolation, in dBTP
+pub fn true_peak_db(audio: &Audio) -> f64 {
+    let peak = audio
+        .split_channels()
+        .iter()
+        .flat_map(|channel| sample_true_peaks(channel))
+        .fold(0.0f32, f32::max);
+
+    20.0 * (peak as f64).max(1e-10).log10()
+}
+
+/// The largest absolute value from every sample to the next one
+fn sample_true_peaks(samples: &[f32]) -> Vec<f32> {
+    let kernel: Vec<Vec<f32>> = (1..OVERSAMPLE)
+        .map(|phase| {
+            let frac = phase as f64 / OVERSAMPLE as f64;
+            (-TAPS + 1..=TAPS)
+                .map(|k| {
+                    let t = frac - k as f64;
+                    let sinc = if t == 0.0 {
+                        1.0
+                    } else {
+                        (PI * t).sin() / (PI * t)
+                    };
+                    // Hann window over the filter
+                    let window = 0.5 + 0.5 * (PI * t / TAPS as f64).cos();
+                    (sinc * window) as f32
+                })
+                .collect()
+        })
+        .collect();
+
+    let at = |i: isize| {
+        if i < 0 || i as usize >= samples.len() {
+            0.0
+        } else {
+            samples[i as usize]
+        }
+    };
+
+    (0..samples.len() as isize)
+        .map(|i| {
+            kernel
+                .iter()
+                .map(|taps| {
+                    taps.iter()
+                        .zip(-TAPS + 1..=TAPS)
+                        .map(|(h, k)| h * at(i + k))
+                        .sum::<f32>()
+                        .abs()
+                })
+                .fold(at(i).abs(), f32::max)
+        })
+        .collect()
+}
+
+/// Look ahead of the limiter, the gain is down before a peak arrives
+const LOOKAHEAD_MS: u64 = 5;
+
+/// Lookahead limiter keeping the true peak of every channel under `ceiling`.
+///
+/// The needed gain of every sample is spread by a moving minimum and smoothed by a
+/// moving average of the same width, so the gain is already low enough at each peak
+/// and changes without clicks. The channels share the gain to keep the stereo image.
+fn limit(audio: &mut Audio, ceiling: f32) {
+    let channels = audio.channels.max(1) as usize;
+    let frames = audio.frames();
+
+    let mut needed = vec![1.0f32; frames];
+    for channel in audio.split_channels() {
+        for (need, peak) in needed.iter_mut().zip(sample_true_peaks(&channel)) {
+            if peak > ceiling {
+                *need = need.min(ceiling / peak);
+            }
+        }
+    }
+    if needed.iter().all(|&g| g >= 1.0) {
+        return;
+    }
+
+    let half = (audio.sample_rate as u64 * LOOKAHEAD_MS / 1000) as usize;
+    let spread = moving_min(&needed, half);
+    let gains = moving_average(&spread, half);
+
+    for (frame, gain) in audio.samples.chunks_mut(channels).zip(gains) {
+        frame.iter_mut().for_each(|s| *s *= gain);
+    }
+}
+
+/// Minimum of every `[i - half, i + half]`
+fn moving_min(values: &[f32], half: usize) -> Vec<f32> {
+    let mut window: VecDeque<usize> = VecDeque::new();
+    let mut out = Vec::with_capacity(values.len());
+
+    for i in 0..values.len() + half {
+        if i < values.len() {
+            while window.back().is_some_and(|&j| values[j] >= values[i]) {
+                window.pop_back();
+            }
+            window.push_back(i);
+        }
+        if i >= half {
+            let center = i - half;
+            while window.front().is_some_and(|&j| j + half < center) {
+                window.pop_front();
+            }
+            out.push(values[window[0]]);
+        }
+    }
+
+    out
+}
+
+/// Mean of every `[i - half, i + half]`, cut at the edges
+fn moving_average(values: &[f32], half: usize) -> Vec<f32> {
+    let mut sums = vec![0.0f64; values.len() + 1];
+    for (i, v) in values.iter().enumerate() {
+        sums[i + 1] = sums[i] + *v as f64;
+    }
+
+    (0..values.len())
+        .map(|i| {
+            let from = i.saturating_sub(half);
+            let to = (i + half + 1).min(values.len());
+            ((sums[to] - sums[from]) / (to - from) as f64) as f32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    fn sine(hz: f64, amplitude: f64, secs: f64) -> Audio {
+        let samples = (0..(RATE as f64 * secs) as usize)
+            .map(|i| (amplitude * (2.0 * PI * hz * i as f64 / RATE as f64).sin()) as f32)
+            .collect();
+        Audio::from_channels(RATE, vec![samples])
+    }
+
+    #[test]
+    fn sine_at_minus_20_dbfs() {
+        // a 1 kHz sine reads about 3 dB below its peak level
+        let lufs = integrated_loudness(&sine(1000.0, 0.1, 3.0)).unwrap();
+
+        assert!((lufs + 23.0).abs() < 0.1, "got {lufs}");
+    }
+
+    #[test]
+    fn stereo_sums_channels() {
+        let mono = sine(1000.0, 0.1, 2.0);
+        let stereo = Audio::from_channels(RATE, vec![mono.samples.clone(), mono.samples]);
+
+        let lufs = integrated_loudness(&stereo).unwrap();
+
+        assert!((lufs + 20.0).abs() < 0.1, "got {lufs}");
+    }
+
+    #[test]
+    fn silence_is_gated() {
+        let silence = Audio::from_channels(RATE, vec![vec![0.0; RATE as usize]]);
+
+        assert_eq!(integrated_loudness(&silence), None);
+    }
+
+    #[test]
+    fn peak_between_samples() {
+        // samples at 45° and 135° of a sine at fs/4, its true peak is 3 dB above them
+        let samples = (0..4800)
+            .map(|i| (0.5 * (PI / 2.0 * i as f64 + PI / 4.0).sin()) as f32)
+            .collect();
+        let audio = Audio::from_channels(RATE, vec![samples]);
+
+        let sample_peak_db = 20.0 * (0.5f64 * 0.5f64.sqrt()).log10();
+        assert!((true_peak_db(&audio) - (sample_peak_db + 3.01)).abs() < 0.3);
+    }
+
+    #[test]
+    fn normalize_to_target() {
+        let mut audio = sine(440.0, 0.05, 3.0);
+        let config = LoudnessConfig::default();
+
+        let report = normalize(&mut audio, &config);
+
+        assert!((report.output_lufs.unwrap() - config.target_lufs).abs() < 0.2);
+        assert!(report.output_true_peak_db <= config.true_peak_db + 0.1);
+        assert!(report.gain_db > 0.0);
+    }
+
+    #[test]
+    fn limit_the_true_peak() {
+        // a quiet tone with a loud burst, the gain for the tone pushes the burst over
+        let mut audio = sine(440.0, 0.1, 3.0);
+        for s in &mut audio.samples[RATE as usize..RATE as usize + 2400] {
+            *s *= 8.0;
+        }
+        let config = LoudnessConfig::default();
+
+        let report = normalize(&mut audio, &config);
+
+        assert!(report.output_true_peak_db <= config.true_peak_db + 0.1);
+        assert!(report.input_true_peak_db + report.gain_db > config.true_peak_db);
+    }
+}
@@ -0,0 +1,283 @@
+pub mod assemble;
+pub mod effects;
+pub mod fit;
+pub mod loudness;
+pub mod music;
+pub mod sfx;
+
+use std::{io, path::Path, time::Duration};
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use thiserror::Error;
+
+pub type AudioResult<T> = Result<T, AudioError>;
+
+/// Length of the windows silence is detected on
+const SILENCE_WINDOW_MS: u64 = 10;
+
+#[derive(Error, Debug)]
+pub enum AudioError {
+    #[error("WAV error: {0}")]
+    Wav(String),
+    #[error("no audio to assemble")]
+    Empty,
+    #[error("IO error: {0}")]
+    IO(#[from] io::Error),
+}
+
+impl From<hound::Error> for AudioError {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::IoError(e) => Self::IO(e),
+            e => Self::Wav(e.to_string()),
+        }
+    }
+}
+
+/// Sample rate and channel count
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioSpec {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Decoded audio, samples in -1.0 ~ 1.0, channels interleaved
+#[derive(Clone, Debug, PartialEq)]
+pub struct Audio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl Audio {
+    /// Read a WAV of any bit depth, integer or float
+    pub fn read(path: impl AsRef<Path>) -> AudioResult<Self> {
+        let mut reader = WavReader::open(path)?;
+        let spec = reader.spec();
+
+        let samples = match spec.sample_format {
+            SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|s| s.map(|s| s as f32 / scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+
+        Ok(Self {
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            samples,
+        })
+    }
+
+    /// Write as 16 bit PCM, samples out of range are clipped
+    pub fn write(&self, path: impl AsRef<Path>) -> AudioResult<()> {
+        let spec = WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+
+        let mut writer = WavWriter::create(path, spec)?;
+        for sample in &self.samples {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize()?;
+
+        Ok(())
+    }
+
+    pub fn spec(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+        }
+    }
+
+    /// Resample and mix the channels to `spec`
+    pub fn convert(self, spec: AudioSpec) -> Self {
+        if self.spec() == spec {
+            return self;
+        }
+
+        let channels = mix_channels(self.split_channels(), spec.channels.max(1) as usize);
+        let rate = self.sample_rate as f64 / spec.sample_rate as f64;
+        let channels = channels
+            .into_iter()
+            .map(|channel| resample(&channel, rate))
+            .collect();
+
+        Self::from_channels(spec.sample_rate, channels)
+    }
+
+    /// Number of samples of every channel
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate as f64)
+    }
+
+    /// Cut, or pad with silence, to `duration`
+    pub fn set_duration(&mut self, duration: Duration) {
+        let frames = (duration.as_secs_f64() * self.sample_rate as f64).round() as usize;
+        self.samples
+            .resize(frames * self.channels.max(1) as usize, 0.0);
+    }
+
+    /// Cut the silence at both ends, keeping `padding` of it,
+    /// with the length cut from the start. Audio silent all along is kept as is.
+    pub fn trim_silence(self, threshold_db: f64, padding: Duration) -> (Self, Duration) {
+        let channels = self.channels.max(1) as usize;
+        let window = ((self.sample_rate as u64 * SILENCE_WINDOW_MS / 1000) as usize).max(1);
+        let threshold = 10f32.powf(threshold_db as f32 / 20.0);
+
+        let voiced: Vec<usize> = self
+            .samples
+            .chunks(window * channels)
+            .enumerate()
+            .filter(|(_, chunk)| {
+                let rms = (chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32).sqrt();
+                rms >= threshold
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let (Some(first), Some(last)) = (voiced.first(), voiced.last()) else {
+            return (self, Duration::ZERO);
+        };
+
+        let padding = (padding.as_secs_f64() * self.sample_rate as f64) as usize;
+        let frames = self.frames();
+        let start = (first * window).saturating_sub(padding);
+        let end = ((last + 1) * window + padding).min(frames);
+
+        let trimmed = Duration::from_nanos(start as u64 * 1_000_000_000 / self.sample_rate as u64);
+        let audio = Self {
+            samples: self.samples[start * channels..end * channels].to_vec(),
+            ..self
+        };
+
+        (audio, trimmed)
+    }
+
+    /// Every channel on its own
+    pub fn split_channels(&self) -> Vec<Vec<f32>> {
+        let channels = self.channels.max(1) as usize;
+        (0..channels)
+            .map(|c| {
+                self.samples
+                    .iter()
+                    .skip(c)
+                    .step_by(channels)
+                    .copied()
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The reverse of [`Audio::split_channels`], cut to the shortest channel
+    pub fn from_channels(sample_rate: u32, channels: Vec<Vec<f32>>) -> Self {
+        let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+        let mut samples = Vec::with_capacity(frames * channels.len());
+        for i in 0..frames {
+            samples.extend(channels.iter().map(|channel| channel[i]));
+        }
+
+        Self {
+            sample_rate,
+            channels: channels.len() as u16,
+            samples,
+        }
+    }
+}
+
+/// Down mixing averages the channels folded onto the same output,
+/// up mixing repeats them, so mono is copied to every channel.
+fn mix_channels(channels: Vec<Vec<f32>>, to: usize) -> Vec<Vec<f32>> {
+    let from = channels.len();
+    if from == to || from == 0 {
+        return channels;
+    }
+    if from < to {
+        return (0..to).map(|c| channels[c % from].clone()).collect();
+    }
+
+    let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+    (0..to)
+        .map(|c| {
+            let folded: Vec<&Vec<f32>> = channels.iter().skip(c).step_by(to).collect();
+            (0..frames)
+                .map(|i| folded.iter().map(|channel| channel[i]).sum::<f32>() / folded.len() as f32)
+                .collect()
+        })
+        .collect()
+}
+
+/// Play `rate` times faster by linear interpolation, pitch and tempo change together.
+/// Also converts the sample rate, by the ratio of the rates.
+pub(crate) fn resample(samples: &[f32], rate: f64) -> Vec<f32> {
+    if samples.is_empty() || rate == 1.0 {
+        return samples.to_vec();
+    }
+
+    let len = (samples.len() as f64 / rate) as usize;
+    let last = samples.len() - 1;
+
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * rate;
+            let k = (pos as usize).min(last);
+            let frac = (pos - k as f64) as f32;
+            let next = samples[(k + 1).min(last)];
+            samples[k] + (next - samples[k]) * frac
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::{DEFAULT_TEMP_DIR, Workspace};
+
+    #[test]
+    fn write_and_read() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = workspace.path("stereo.wav");
+        let audio = Audio::from_channels(8000, vec![vec![0.5; 800], vec![-0.25; 800]]);
+
+        audio.write(&path).unwrap();
+        let read = Audio::read(&path).unwrap();
+
+        assert_eq!(read.channels, 2);
+        assert_eq!(read.duration(), Duration::from_millis(100));
+        let channels = read.split_channels();
+        assert!((channels[0][10] - 0.5).abs() < 1e-3);
+        assert!((channels[1][10] + 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn convert_rate_and_channels() {
+        let stereo = Audio::from_channels(8000, vec![vec![0.5; 800], vec![0.1; 800]]);
+
+        let mono = stereo.convert(AudioSpec {
+            sample_rate: 16000,
+            channels: 1,
+        });
+
+        assert_eq!(mono.channels, 1);
+        assert_eq!(mono.frames(), 1600);
+        assert!((mono.samples[100] - 0.3).abs() < 1e-6);
+
+        let stereo = mono.convert(AudioSpec {
+            sample_rate: 16000,
+            channels: 2,
+        });
+        assert_eq!(stereo.channels, 2);
+        assert!(stereo.samples[..4].iter().all(|s| (s - 0.3).abs() < 1e-6));
+    }
+}
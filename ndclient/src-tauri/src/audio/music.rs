@@ -0,0 +1,395 @@
+use std::{
+    f32::consts::FRAC_PI_2,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{
+        Audio, AudioResult,
+        loudness::{db_to_gain, integrated_loudness},
+    },
+    timeline::Timeline,
+};
+
+pub const DEFAULT_MUSIC_DIR: &str = "./music";
+
+/// Overlap of the end and the start of a looped track, so the seam does not click
+const LOOP_CROSSFADE: Duration = Duration::from_millis(50);
+
+/// Background music under the dubbing, off by default
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MusicConfig {
+    pub enabled: bool,
+    /// the library, WAV files of any format
+    pub dir: PathBuf,
+    /// file name in `dir`, empty picks one at random
+    pub track: String,
+    /// loudness of the music relative to the speech, in LU
+    pub level_db: f64,
+    /// more attenuation while a sentence is spoken
+    pub duck_db: f64,
+    /// the music is down this long before a sentence starts
+    pub duck_attack_ms: u64,
+    /// and comes back up this long after it ends
+    pub duck_release_ms: u64,
+    pub fade_in_ms: u64,
+    pub fade_out_ms: u64,
+}
+
+impl Default for MusicConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: PathBuf::from(DEFAULT_MUSIC_DIR),
+            track: String::new(),
+            level_db: -12.0,
+            duck_db: -10.0,
+            duck_attack_ms: 200,
+            duck_release_ms: 500,
+            fade_in_ms: 1000,
+            fade_out_ms: 2000,
+        }
+    }
+}
+
+impl MusicConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.dir.as_os_str().is_empty() {
+            return Err("dir cannot be empty".to_owned());
+        }
+        if self.track.contains(['/', '\\']) {
+            return Err(format!(
+                "track must be a file name in dir, got \"{}\"",
+                self.track
+            ));
+        }
+        if !(-40.0..=0.0).contains(&self.level_db) {
+            return Err(format!(
+                "level_db must be in -40 ~ 0, got {}",
+                self.level_db
+            ));
+        }
+        if !(-40.0..=0.0).contains(&self.duck_db) {
+            return Err(format!("duck_db must be in -40 ~ 0, got {}", self.duck_db));
+        }
+
+        Ok(())
+    }
+}
+
+/// The folder of the music tracks
+#[derive(Clone, Debug)]
+pub struct MusicLibrary {
+    dir: PathBuf,
+}
+
+impl MusicLibrary {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// File names of the WAV tracks, sorted, none if the folder does not exist
+    pub fn tracks(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        let mut tracks = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            let is_wav = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"));
+            if is_wav && path.is_file() {
+                tracks.extend(
+                    path.file_name()
+                        .map(|name| name.to_string_lossy().into_owned()),
+                );
+            }
+        }
+        tracks.sort();
+
+        Ok(tracks)
+    }
+
+    /// Path of `track`, a random one if empty, `None` when the library has none
+    pub fn pick(&self, track: &str) -> io::Result<Option<PathBuf>> {
+        if !track.is_empty() {
+            let path = self.dir.join(track);
+            return if path.is_file() {
+                Ok(Some(path))
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no music track {}", path.display()),
+                ))
+            };
+        }
+
+        let tracks = self.tracks()?;
+        Ok(tracks
+            .choose(&mut rand::thread_rng())
+            .map(|track| self.dir.join(track)))
+    }
+}
+
+/// Mixes a music track under the speech
+pub struct MusicBed {
+    config: MusicConfig,
+}
+
+impl MusicBed {
+    pub fn new(config: MusicConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn mix_file(
+        &self,
+        speech: &Audio,
+        timeline: &Timeline,
+        music: impl AsRef<Path>,
+    ) -> AudioResult<Audio> {
+        Ok(self.mix(speech, timeline, Audio::read(music)?))
+    }
+
+    /// The music is looped or cut to the length of the speech, faded in and out,
+    /// and ducked at every sentence of `timeline`
+    pub fn mix(&self, speech: &Audio, timeline: &Timeline, music: Audio) -> Audio {
+        let music = music.convert(speech.spec());
+        if music.samples.is_empty() {
+            return speech.clone();
+        }
+
+        let fitted = Audio {
+            samples: looped(&music, speech.samples.len()),
+            ..music
+        };
+
+        // relative to the speech, so the balance holds whatever the voice level is
+        let level_db = match (integrated_loudness(speech), integrated_loudness(&fitted)) {
+            (Some(speech), Some(music)) => speech + self.config.level_db - music,
+            _ => self.config.level_db,
+        };
+        let level = db_to_gain(level_db) as f32;
+
+        let channels = speech.channels.max(1) as usize;
+        let gains = self.envelope(speech.sample_rate, speech.frames(), timeline);
+
+        let samples = speech
+            .samples
+            .chunks(channels)
+            .zip(fitted.samples.chunks(channels))
+            .zip(gains)
+            .flat_map(|((speech, music), gain)| {
+                speech
+                    .iter()
+                    .zip(music)
+                    .map(move |(s, m)| s + m * level * gain)
+            })
+            .collect();
+
+        Audio {
+            samples,
+            ..speech.clone()
+        }
+    }
+
+    /// Gain of the music at every frame, fades and ducking together
+    fn envelope(&self, sample_rate: u32, frames: usize, timeline: &Timeline) -> Vec<f32> {
+        let frame = |time: Duration| (time.as_secs_f64() * sample_rate as f64) as usize;
+        let ms = |ms: u64| frame(Duration::from_millis(ms));
+
+        // how much ducked, 0 ~ 1
+        let mut duck = vec![0.0f32; frames];
+        let (attack, release) = (
+            ms(self.config.duck_attack_ms),
+            ms(self.config.duck_release_ms),
+        );
+        for segment in &timeline.segments {
+            let (start, end) = (frame(segment.start), frame(segment.end()));
+            let ramp_from = start.saturating_sub(attack);
+
+            for (i, d) in duck
+                .iter_mut()
+                .enumerate()
+                .take((end + release).min(frames))
+                .skip(ramp_from)
+            {
+                let amount = if i < start {
+                    (i - ramp_from) as f32 / attack as f32
+                } else if i < end {
+                    1.0
+                } else {
+                    1.0 - (i - end) as f32 / release as f32
+                };
+                *d = d.max(amount);
+            }
+        }
+
+        let (fade_in, fade_out) = (ms(self.config.fade_in_ms), ms(self.config.fade_out_ms));
+        let duck_db = self.config.duck_db;
+
+        duck.into_iter()
+            .enumerate()
+            .map(|(i, amount)| {
+                let fade = if i < fade_in {
+                    i as f32 / fade_in as f32
+                } else if frames - i < fade_out {
+                    (frames - i) as f32 / fade_out as f32
+                } else {
+                    1.0
+                };
+                fade * db_to_gain(duck_db * amount as f64) as f32
+            })
+            .collect()
+    }
+}
+
+/// `len` samples of `music`, repeated with a crossfade at every seam
+fn looped(music: &Audio, len: usize) -> Vec<f32> {
+    let channels = music.channels.max(1) as usize;
+    let frames = music.frames();
+    let mut fade = (LOOP_CROSSFADE.as_secs_f64() * music.sample_rate as f64) as usize * channels;
+    // too short a track to overlap
+    if frames * channels < fade * 2 {
+        fade = 0;
+    }
+
+    let mut out = music.samples.clone();
+    while out.len() < len {
+        let seam = out.len() - fade;
+        for (i, (o, m)) in out[seam..].iter_mut().zip(&music.samples).enumerate() {
+            // equal power, the end and the start of a track are not alike
+            let t = (i / channels) as f32 / (fade / channels) as f32 * FRAC_PI_2;
+            *o = *o * t.cos() + m * t.sin();
+        }
+        out.extend_from_slice(&music.samples[fade..]);
+    }
+    out.truncate(len);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        news::SpeakerRole,
+        timeline::Segment,
+        workspace::{DEFAULT_TEMP_DIR, Workspace},
+    };
+
+    const RATE: u32 = 8000;
+
+    fn config() -> MusicConfig {
+        MusicConfig {
+            level_db: -20.0,
+            duck_db: -20.0,
+            duck_attack_ms: 100,
+            duck_release_ms: 100,
+            fade_in_ms: 100,
+            fade_out_ms: 100,
+            ..MusicConfig::default()
+        }
+    }
+
+    #[test]
+    fn loop_without_click() {
+        // a ramp from 0 to 1, a loop without crossfade jumps from 1 to 0
+        let ramp = (0..RATE).map(|i| i as f32 / RATE as f32).collect();
+        let music = Audio::from_channels(RATE, vec![ramp]);
+
+        let samples = looped(&music, RATE as usize * 3);
+
+        assert_eq!(samples.len(), RATE as usize * 3);
+        let max_step = samples
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(max_step < 0.01, "step of {max_step}");
+    }
+
+    fn at(audio: &Audio, ms: u64) -> f32 {
+        audio.samples[(ms * RATE as u64 / 1000) as usize]
+    }
+
+    #[test]
+    fn loop_fade_and_duck() {
+        // silent speech, so the level follows `level_db` only
+        let speech = Audio::from_channels(RATE, vec![vec![0.0; RATE as usize * 3]]);
+        let music = Audio::from_channels(RATE, vec![vec![1.0; RATE as usize]]);
+        let segment = Segment {
+            text: "你好".to_owned(),
+            start: Duration::from_millis(1000),
+            duration: Duration::from_millis(1000),
+            words: None,
+            role: SpeakerRole::Anchor,
+            emphasis: false,
+        };
+        let timeline = Timeline::new(vec![segment], speech.duration());
+
+        let mixed = MusicBed::new(config()).mix(&speech, &timeline, music);
+
+        assert_eq!(mixed.frames(), speech.frames());
+        assert_eq!(at(&mixed, 0), 0.0);
+        assert!((at(&mixed, 50) - 0.05).abs() < 1e-3);
+        assert!((at(&mixed, 500) - 0.1).abs() < 1e-3);
+        // half way down the attack, a full duck is 0.01
+        assert!(at(&mixed, 950) < 0.1 && at(&mixed, 950) > 0.01);
+        assert!((at(&mixed, 1500) - 0.01).abs() < 1e-3);
+        assert!((at(&mixed, 2500) - 0.1).abs() < 1e-3);
+        assert!(at(&mixed, 2975) < 0.05);
+    }
+
+    #[test]
+    fn level_relative_to_speech() {
+        let tone = |amplitude: f32| {
+            (0..RATE as usize * 2)
+                .map(|i| amplitude * (i as f32 * 0.3).sin())
+                .collect::<Vec<f32>>()
+        };
+        let speech = Audio::from_channels(RATE, vec![tone(0.5)]);
+        let music = Audio::from_channels(RATE, vec![tone(0.05)]);
+        let config = MusicConfig {
+            duck_db: 0.0,
+            fade_in_ms: 0,
+            fade_out_ms: 0,
+            ..config()
+        };
+
+        let mixed = MusicBed::new(config).mix(&speech, &Timeline::default(), music);
+
+        // the tones are in phase, the music adds a tenth of the speech
+        assert!((at(&mixed, 1000) - at(&speech, 1000) * 1.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn library_tracks() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let library = MusicLibrary::new(workspace.path("music"));
+        assert!(library.tracks().unwrap().is_empty());
+        assert_eq!(library.pick("").unwrap(), None);
+
+        fs::create_dir(workspace.path("music")).unwrap();
+        let audio = Audio::from_channels(RATE, vec![vec![0.0; 80]]);
+        audio.write(workspace.path("music/b.wav")).unwrap();
+        audio.write(workspace.path("music/a.WAV")).unwrap();
+        fs::write(workspace.path("music/notes.txt"), "").unwrap();
+
+        assert_eq!(library.tracks().unwrap(), ["a.WAV", "b.wav"]);
+        assert_eq!(
+            library.pick("b.wav").unwrap(),
+            Some(workspace.path("music/b.wav"))
+        );
+        assert!(library.pick("").unwrap().is_some());
+        assert!(library.pick("c.wav").is_err());
+    }
+}
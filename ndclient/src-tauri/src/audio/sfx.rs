@@ -0,0 +1,213 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{Audio, AudioResult, loudness::db_to_gain};
+
+pub const DEFAULT_SFX_DIR: &str = "./sfx";
+
+/// Moments of the video a sound effect can be played at
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SfxEvent {
+    /// the very beginning
+    VideoStart,
+    /// every change of picture
+    Transition,
+    /// the start of a sentence marked as emphasis
+    Emphasis,
+}
+
+/// A sound effect and the event playing it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SoundEffect {
+    pub event: SfxEvent,
+    /// WAV file name in the sfx folder
+    pub file: String,
+    #[serde(default)]
+    pub gain_db: f64,
+    /// moves it from the event, negative to lead into it, like a whoosh before a transition
+    #[serde(default)]
+    pub offset_ms: i64,
+}
+
+/// Short sound effects mixed into the dubbing
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SfxConfig {
+    pub enabled: bool,
+    pub dir: PathBuf,
+    pub effects: Vec<SoundEffect>,
+}
+
+impl Default for SfxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: PathBuf::from(DEFAULT_SFX_DIR),
+            effects: vec![],
+        }
+    }
+}
+
+impl SfxConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for effect in &self.effects {
+            if effect.file.is_empty() || effect.file.contains(['/', '\\']) {
+                return Err(format!(
+                    "file must be a file name in dir, got \"{}\"",
+                    effect.file
+                ));
+            }
+            if !(-40.0..=12.0).contains(&effect.gain_db) {
+                return Err(format!(
+                    "gain_db of {} must be in -40 ~ 12, got {}",
+                    effect.file, effect.gain_db
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An event happening at a time of the video
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cue {
+    pub event: SfxEvent,
+    pub at: Duration,
+}
+
+impl Cue {
+    pub fn new(event: SfxEvent, at: Duration) -> Self {
+        Self { event, at }
+    }
+}
+
+/// Plays the configured sound effects at the cues
+pub struct SfxMixer {
+    config: SfxConfig,
+}
+
+impl SfxMixer {
+    pub fn new(config: SfxConfig) -> Self {
+        Self { config }
+    }
+
+    /// Mix the effects of every cue into `audio`, cut at its end.
+    /// Every file is read once, in the format of `audio`.
+    pub fn mix(&self, audio: &mut Audio, cues: &[Cue]) -> AudioResult<()> {
+        let mut loaded: HashMap<&str, Audio> = HashMap::new();
+        for effect in &self.config.effects {
+            if !loaded.contains_key(effect.file.as_str()) {
+                let sound = Audio::read(self.config.dir.join(&effect.file))?.convert(audio.spec());
+                loaded.insert(&effect.file, sound);
+            }
+        }
+
+        for effect in &self.config.effects {
+            let sound = &loaded[effect.file.as_str()];
+            let gain = db_to_gain(effect.gain_db) as f32;
+
+            for cue in cues.iter().filter(|cue| cue.event == effect.event) {
+                let at = cue.at.as_millis() as i64 + effect.offset_ms;
+                add(audio, sound, Duration::from_millis(at.max(0) as u64), gain);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Add `sound` of the same spec into `audio` at `at`
+fn add(audio: &mut Audio, sound: &Audio, at: Duration, gain: f32) {
+    let channels = audio.channels.max(1) as usize;
+    let start = (at.as_secs_f64() * audio.sample_rate as f64) as usize * channels;
+    if start >= audio.samples.len() {
+        return;
+    }
+
+    for (a, s) in audio.samples[start..].iter_mut().zip(&sound.samples) {
+        *a += s * gain;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::workspace::{DEFAULT_TEMP_DIR, Workspace};
+
+    const RATE: u32 = 8000;
+
+    fn at(audio: &Audio, ms: u64) -> f32 {
+        audio.samples[(ms * RATE as u64 / 1000) as usize]
+    }
+
+    #[test]
+    fn effects_at_cues() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let dir = workspace.path("sfx");
+        fs::create_dir(&dir).unwrap();
+        // 100 ms each, the ding at 16 kHz is converted
+        Audio::from_channels(RATE, vec![vec![0.5; 800]])
+            .write(dir.join("whoosh.wav"))
+            .unwrap();
+        Audio::from_channels(16_000, vec![vec![0.25; 1600]])
+            .write(dir.join("ding.wav"))
+            .unwrap();
+
+        let mixer = SfxMixer::new(SfxConfig {
+            enabled: true,
+            dir,
+            effects: vec![
+                SoundEffect {
+                    event: SfxEvent::Transition,
+                    file: "whoosh.wav".to_owned(),
+                    gain_db: 0.0,
+                    offset_ms: -50,
+                },
+                SoundEffect {
+                    event: SfxEvent::Emphasis,
+                    file: "ding.wav".to_owned(),
+                    gain_db: 6.0,
+                    offset_ms: 0,
+                },
+            ],
+        });
+        let mut audio = Audio::from_channels(RATE, vec![vec![0.0; RATE as usize]]);
+        let cues = [
+            Cue::new(SfxEvent::VideoStart, Duration::ZERO),
+            Cue::new(SfxEvent::Transition, Duration::from_millis(300)),
+            Cue::new(SfxEvent::Emphasis, Duration::from_millis(600)),
+            // cut at the end
+            Cue::new(SfxEvent::Transition, Duration::from_millis(950)),
+        ];
+
+        mixer.mix(&mut audio, &cues).unwrap();
+
+        assert_eq!(audio.frames(), RATE as usize);
+        assert_eq!(at(&audio, 100), 0.0);
+        assert!((at(&audio, 260) - 0.5).abs() < 1e-3);
+        assert_eq!(at(&audio, 360), 0.0);
+        assert!((at(&audio, 650) - 0.5).abs() < 1e-2);
+        assert!((at(&audio, 990) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn missing_file() {
+        let mixer = SfxMixer::new(SfxConfig {
+            effects: vec![SoundEffect {
+                event: SfxEvent::VideoStart,
+                file: "nothing.wav".to_owned(),
+                gain_db: 0.0,
+                offset_ms: 0,
+            }],
+            ..SfxConfig::default()
+        });
+        let mut audio = Audio::from_channels(RATE, vec![vec![0.0; 800]]);
+
+        assert!(mixer.mix(&mut audio, &[]).is_err());
+    }
+}
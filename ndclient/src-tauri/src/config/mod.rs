@@ -0,0 +1,653 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    audio::{
+        assemble::AssemblyConfig, effects::EffectChain, fit::LengthConfig,
+        loudness::LoudnessConfig, music::MusicConfig, sfx::SfxConfig,
+    },
+    director::output::{DEFAULT_NAME_TEMPLATE, DEFAULT_OUTPUT_DIR},
+    news::SpeakerRole,
+    retry::RetryPolicy,
+    subtitle::{
+        SubtitleMode,
+        ass::{self, AssStyle},
+    },
+    tts::{
+        ali_tts::AliTTSOptions, cache::TTSCacheConfig, limiter::SynthesisLimits,
+        local_tts::LocalTTSConfig, normalize::NormalizeConfig,
+    },
+    video::VideoFormat,
+    workspace::DEFAULT_TEMP_DIR,
+};
+
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// news sources which can be selected in `sources.news`
+pub const KNOWN_SOURCES: &[&str] = &["pengpai"];
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("config file error: {0}")]
+    File(#[from] io::Error),
+    #[error("cannot parse config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("cannot serialize config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("invalid config `{field}`: {reason}")]
+    Invalid { field: &'static str, reason: String },
+}
+
+/// Application configuration, stored as TOML.
+///
+/// Missing fields take their default value, and some fields can be overridden
+/// by environment variables, see [`AppConfig::apply_env`].
+/// API keys are not part of it, they live in the encrypted [`crate::secrets::SecretStore`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub sources: SourcesConfig,
+    pub llm: LlmConfig,
+    pub tts: TtsConfig,
+    pub video: VideoFormat,
+    pub subtitle: SubtitleConfig,
+    /// how the sentences are joined into the dubbing
+    pub dubbing: AssemblyConfig,
+    /// length to fit the video to
+    pub length: LengthConfig,
+    /// loudness normalisation of the dubbing
+    pub loudness: LoudnessConfig,
+    /// background music under the dubbing
+    pub music: MusicConfig,
+    /// sound effects at the events of the video
+    pub sfx: SfxConfig,
+    pub paths: PathsConfig,
+    pub concurrency: ConcurrencyConfig,
+    /// retry of the TTS requests and picture downloads
+    pub retry: RetryPolicy,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SourcesConfig {
+    /// which news source the hot news come from
+    pub news: String,
+}
+
+impl Default for SourcesConfig {
+    fn default() -> Self {
+        Self {
+            news: "pengpai".to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LlmConfig {
+    /// OpenAI compatible API, DeepSeek by default
+    pub base_url: String,
+    pub model: String,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://api.deepseek.com".to_owned(),
+            model: "deepseek-chat".to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtsProvider {
+    /// Ali DashScope
+    Ali,
+    /// our self-hosted `tts_service`
+    Http,
+    /// a CPU engine run as a subprocess, works offline
+    Local,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TtsConfig {
+    pub provider: TtsProvider,
+    pub url: String,
+    pub cache: TTSCacheConfig,
+    /// numbers and lexicon before synthesis
+    pub normalize: NormalizeConfig,
+    /// voice of the quoted speakers, the roles not listed are read by the anchor.
+    /// Not supported by the `http` provider.
+    pub roles: BTreeMap<SpeakerRole, String>,
+    /// effects on every clip, can be picked per video, empty for the original voice
+    pub effects: EffectChain,
+    /// command line of the `local` provider
+    pub local: LocalTTSConfig,
+    /// default voice of Ali TTS, can be changed per video
+    #[serde(flatten)]
+    pub ali: AliTTSOptions,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            provider: TtsProvider::Ali,
+            url: "https://dashscope.aliyuncs.com/api/v1/services/aigc/multimodal-generation/generation"
+                .to_owned(),
+            cache: TTSCacheConfig::default(),
+            normalize: NormalizeConfig::default(),
+            roles: BTreeMap::new(),
+            effects: EffectChain::carton(),
+            local: LocalTTSConfig::default(),
+            ali: AliTTSOptions::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    /// plain, in the default look of the player
+    Srt,
+    /// styled by a preset of `style`
+    Ass,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubtitleConfig {
+    /// `soft` muxes an SRT track and saves `.srt` and `.vtt` files beside the video, `format` must be SRT
+    pub mode: SubtitleMode,
+    /// of the burnt in subtitles
+    pub format: SubtitleFormat,
+    /// id of a preset of [`ass::presets`], for the ASS format
+    pub style: String,
+    /// long sentences are split into cues of at most this many characters, `0` keeps them whole
+    pub max_chars: usize,
+    /// `#RRGGBB` of the subtitles of every speaker role, the roles not listed keep the default
+    pub colors: BTreeMap<SpeakerRole, String>,
+}
+
+impl Default for SubtitleConfig {
+    fn default() -> Self {
+        Self {
+            mode: SubtitleMode::Burn,
+            format: SubtitleFormat::Srt,
+            style: ass::DEFAULT_STYLE.to_owned(),
+            max_chars: 16,
+            colors: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    /// base directory of the job workspaces
+    pub temp_dir: PathBuf,
+    /// where the final videos are stored
+    pub output_dir: PathBuf,
+    /// final video file name, supports `{date}`, `{source}`, `{slug}` and `{id}`
+    pub name_template: String,
+    /// keep the job workspaces for debugging
+    pub keep_artifacts: bool,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            temp_dir: PathBuf::from(DEFAULT_TEMP_DIR),
+            output_dir: PathBuf::from(DEFAULT_OUTPUT_DIR),
+            name_template: DEFAULT_NAME_TEMPLATE.to_owned(),
+            keep_artifacts: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// how many videos can be generated at the same time
+    pub max_jobs: usize,
+    /// sentences synthesised in parallel for one video
+    pub tts: SynthesisLimits,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_jobs: 1,
+            tts: SynthesisLimits::default(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Load the config file, a missing file means the default config.
+    /// Environment overrides are applied, but the result is not validated.
+    pub fn load(path: impl AsRef<Path>) -> ConfigResult<Self> {
+        let mut config = match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        config.apply_env();
+
+        Ok(config)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> ConfigResult<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, toml::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Override the config by the environment variables, which are used before the config file existed:
+    /// `OPENAI_BASE_URL`, `TTS_URL`, `TEMP_DIR`, `OUTPUT_DIR`, `OUTPUT_NAME_TEMPLATE` and `KEEP_ARTIFACTS`
+    pub fn apply_env(&mut self) {
+        self.apply_vars(|key| dotenv::var(key).ok());
+    }
+
+    fn apply_vars(&mut self, var: impl Fn(&str) -> Option<String>) {
+        if let Some(v) = var("OPENAI_BASE_URL") {
+            self.llm.base_url = v;
+        }
+        if let Some(v) = var("TTS_URL") {
+            self.tts.url = v;
+        }
+        if let Some(v) = var("TEMP_DIR") {
+            self.paths.temp_dir = PathBuf::from(v);
+        }
+        if let Some(v) = var("OUTPUT_DIR") {
+            self.paths.output_dir = PathBuf::from(v);
+        }
+        if let Some(v) = var("OUTPUT_NAME_TEMPLATE") {
+            self.paths.name_template = v;
+        }
+        if let Some(v) = var("KEEP_ARTIFACTS") {
+            self.paths.keep_artifacts = v == "1" || v == "true";
+        }
+    }
+
+    /// Check the config is usable to generate videos
+    pub fn validate(&self) -> ConfigResult<()> {
+        fn invalid(field: &'static str, reason: impl Into<String>) -> ConfigResult<()> {
+            Err(ConfigError::Invalid {
+                field,
+                reason: reason.into(),
+            })
+        }
+
+        fn is_http_url(url: &str) -> bool {
+            url.starts_with("http://") || url.starts_with("https://")
+        }
+
+        if !KNOWN_SOURCES.contains(&self.sources.news.as_str()) {
+            return invalid(
+                "sources.news",
+                format!(
+                    "unknown source \"{}\", available: {}",
+                    self.sources.news,
+                    KNOWN_SOURCES.join(", ")
+                ),
+            );
+        }
+
+        if !is_http_url(&self.llm.base_url) {
+            return invalid("llm.base_url", "must be a http(s) URL");
+        }
+        if self.llm.model.trim().is_empty() {
+            return invalid("llm.model", "cannot be empty");
+        }
+
+        match self.tts.provider {
+            TtsProvider::Ali | TtsProvider::Http if !is_http_url(&self.tts.url) => {
+                return invalid("tts.url", "must be a http(s) URL, can be set by TTS_URL");
+            }
+            TtsProvider::Ali => {
+                if let Err(reason) = self.tts.ali.validate() {
+                    return invalid("tts", reason);
+                }
+            }
+            // the service picks its own voices
+            TtsProvider::Http if !self.tts.roles.is_empty() => {
+                return invalid("tts.roles", "the http provider has no voices to pick");
+            }
+            TtsProvider::Http => {}
+            TtsProvider::Local => {
+                if let Err(reason) = self.tts.local.validate() {
+                    return invalid("tts.local", reason);
+                }
+            }
+        }
+        if self.tts.cache.enabled && self.tts.cache.dir.as_os_str().is_empty() {
+            return invalid("tts.cache.dir", "cannot be empty");
+        }
+        if let Some((role, _)) = self.tts.roles.iter().find(|(_, v)| v.trim().is_empty()) {
+            return invalid("tts.roles", format!("voice of {role:?} cannot be empty"));
+        }
+        if let Err(reason) = self.tts.effects.validate() {
+            return invalid("tts.effects", reason);
+        }
+
+        if self.video.width == 0 || self.video.height == 0 {
+            return invalid("video", "width and height must be greater than 0");
+        }
+        if self.video.width % 2 != 0 || self.video.height % 2 != 0 {
+            return invalid("video", "width and height must be even for H.264");
+        }
+        if self.video.fps == 0 {
+            return invalid("video.fps", "must be greater than 0");
+        }
+        if self.video.seconds_per_pic == 0 {
+            return invalid("video.seconds_per_pic", "must be greater than 0");
+        }
+
+        let is_color = |c: &str| {
+            c.len() == 7 && c.starts_with('#') && c[1..].chars().all(|c| c.is_ascii_hexdigit())
+        };
+        if let Some((role, color)) = self.subtitle.colors.iter().find(|(_, c)| !is_color(c)) {
+            return invalid(
+                "subtitle.colors",
+                format!("color of {role:?} must be #RRGGBB, got \"{color}\""),
+            );
+        }
+        if self.subtitle.mode == SubtitleMode::Soft && self.subtitle.format == SubtitleFormat::Ass {
+            return invalid(
+                "subtitle.format",
+                "a soft subtitle track is plain SRT, styles need mode \"burn\"",
+            );
+        }
+        if self.subtitle.format == SubtitleFormat::Ass
+            && AssStyle::preset(&self.subtitle.style).is_none()
+        {
+            let available: Vec<&str> = ass::presets().iter().map(|preset| preset.id).collect();
+            return invalid(
+                "subtitle.style",
+                format!(
+                    "unknown style \"{}\", available: {}",
+                    self.subtitle.style,
+                    available.join(", ")
+                ),
+            );
+        }
+
+        if let Err(reason) = self.dubbing.validate() {
+            return invalid("dubbing", reason);
+        }
+        if let Err(reason) = self.length.validate() {
+            return invalid("length", reason);
+        }
+        if let Err(reason) = self.loudness.validate() {
+            return invalid("loudness", reason);
+        }
+        if let Err(reason) = self.music.validate() {
+            return invalid("music", reason);
+        }
+        if let Err(reason) = self.sfx.validate() {
+            return invalid("sfx.effects", reason);
+        }
+
+        if self.paths.temp_dir.as_os_str().is_empty() {
+            return invalid("paths.temp_dir", "cannot be empty");
+        }
+        if self.paths.output_dir.as_os_str().is_empty() {
+            return invalid("paths.output_dir", "cannot be empty");
+        }
+        if self.paths.name_template.contains(['/', '\\']) {
+            return invalid("paths.name_template", "cannot contain path separator");
+        }
+        if !["{slug}", "{id}"]
+            .iter()
+            .any(|p| self.paths.name_template.contains(p))
+        {
+            return invalid(
+                "paths.name_template",
+                "must contain {slug} or {id}, otherwise every video gets the same name",
+            );
+        }
+
+        if self.concurrency.max_jobs == 0 {
+            return invalid("concurrency.max_jobs", "must be greater than 0");
+        }
+        if let Err(reason) = self.concurrency.tts.validate() {
+            return invalid("concurrency.tts", reason);
+        }
+
+        if let Err(reason) = self.retry.validate() {
+            return invalid("retry", reason);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        audio::effects::{Effect, EqPreset},
+        workspace::Workspace,
+    };
+
+    #[test]
+    fn parse_partial_file() {
+        let config: AppConfig = toml::from_str(
+            r##"
+[tts]
+voice = "Cherry"
+
+[tts.cache]
+max_size_mb = 64
+
+[tts.normalize]
+lexicon = "lexicon.toml"
+
+[tts.roles]
+male = "Ethan"
+
+[subtitle]
+mode = "soft"
+
+[subtitle.colors]
+female = "#FF80C0"
+
+[video]
+fps = 25
+
+[concurrency.tts]
+max_concurrent = 2
+"##,
+        )
+        .unwrap();
+
+        assert_eq!(config.tts.ali.voice, "Cherry");
+        assert_eq!(config.tts.ali.model, "qwen-tts");
+        assert_eq!(config.tts.cache.max_size_mb, 64);
+        assert!(config.tts.cache.enabled);
+        assert!(config.tts.normalize.enabled);
+        assert_eq!(config.tts.normalize.lexicon, PathBuf::from("lexicon.toml"));
+        assert_eq!(config.tts.roles[&SpeakerRole::Male], "Ethan");
+        assert_eq!(config.subtitle.mode, SubtitleMode::Soft);
+        assert_eq!(config.subtitle.format, SubtitleFormat::Srt);
+        assert_eq!(config.subtitle.style, "classic");
+        assert_eq!(config.subtitle.colors[&SpeakerRole::Female], "#FF80C0");
+        assert_eq!(config.video.fps, 25);
+        assert_eq!(config.video.width, 720);
+        assert_eq!(config.concurrency.tts.max_concurrent, 2);
+        assert_eq!(config.concurrency.tts.requests_per_second, 5.0);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = workspace.path(CONFIG_FILE_NAME);
+
+        let mut config = AppConfig::default();
+        config.tts.provider = TtsProvider::Http;
+        config.tts.effects.0.push(Effect::Eq {
+            preset: EqPreset::Warm,
+        });
+        config.save(&path).unwrap();
+
+        let loaded: AppConfig = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(config, loaded);
+    }
+
+    #[test]
+    fn env_overrides() {
+        let vars = HashMap::from([
+            ("TTS_URL", "http://localhost:39685/tts"),
+            ("OUTPUT_DIR", "/videos"),
+            ("KEEP_ARTIFACTS", "true"),
+        ]);
+
+        let mut config = AppConfig::default();
+        config.apply_vars(|key| vars.get(key).map(|v| v.to_string()));
+
+        assert_eq!(config.tts.url, "http://localhost:39685/tts");
+        assert_eq!(config.paths.output_dir, PathBuf::from("/videos"));
+        assert!(config.paths.keep_artifacts);
+        assert_eq!(config.llm.base_url, LlmConfig::default().base_url);
+    }
+
+    #[test]
+    fn validate_errors() {
+        assert!(AppConfig::default().validate().is_ok());
+
+        let mut config = AppConfig::default();
+        config.llm.base_url = "api.deepseek.com".to_owned();
+        assert!(matches!(
+            config.validate().unwrap_err(),
+            ConfigError::Invalid {
+                field: "llm.base_url",
+                ..
+            }
+        ));
+
+        let mut config = AppConfig::default();
+        config.sources.news = "unknown".to_owned();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `sources.news`: unknown source \"unknown\", available: pengpai"
+        );
+
+        let mut config = AppConfig::default();
+        config.tts.ali.rate = 3.0;
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `tts`: rate must be in 0.5 ~ 2.0, got 3"
+        );
+
+        let mut config = AppConfig::default();
+        config.tts.provider = TtsProvider::Local;
+        config.tts.url = String::new();
+        assert!(config.validate().is_ok());
+        config.tts.local.args.clear();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `tts.local`: args must contain {output}"
+        );
+
+        let mut config = AppConfig::default();
+        config.tts.provider = TtsProvider::Http;
+        assert!(config.validate().is_ok());
+        config.tts.roles = [(SpeakerRole::Male, "zhiming".to_owned())].into();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `tts.roles`: the http provider has no voices to pick"
+        );
+
+        let mut config = AppConfig::default();
+        config.subtitle.style = "comic".to_owned();
+        assert!(config.validate().is_ok());
+        config.subtitle.format = SubtitleFormat::Ass;
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `subtitle.style`: unknown style \"comic\", available: classic, bold, boxed, top, center"
+        );
+
+        let mut config = AppConfig::default();
+        config.subtitle.mode = SubtitleMode::Soft;
+        assert!(config.validate().is_ok());
+        config.subtitle.format = SubtitleFormat::Ass;
+        assert!(matches!(
+            config.validate().unwrap_err(),
+            ConfigError::Invalid {
+                field: "subtitle.format",
+                ..
+            }
+        ));
+
+        let mut config = AppConfig::default();
+        config.length.max_tempo = 0.8;
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `length`: max_tempo must be in 1 ~ 2, got 0.8"
+        );
+
+        let mut config = AppConfig::default();
+        config.video.width = 721;
+        assert!(config.validate().is_err());
+
+        let mut config = AppConfig::default();
+        config
+            .subtitle
+            .colors
+            .insert(SpeakerRole::Male, "blue".to_owned());
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `subtitle.colors`: color of Male must be #RRGGBB, got \"blue\""
+        );
+
+        let mut config = AppConfig::default();
+        config.dubbing.sample_rate = 100;
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `dubbing`: sample_rate must be 0 or in 8000 ~ 192000, got 100"
+        );
+
+        let mut config = AppConfig::default();
+        config.loudness.true_peak_db = 1.0;
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `loudness`: true_peak_db must be in -9 ~ 0, got 1"
+        );
+
+        let mut config = AppConfig::default();
+        config.music.track = "../secret.wav".to_owned();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `music`: track must be a file name in dir, got \"../secret.wav\""
+        );
+
+        let mut config = AppConfig::default();
+        config.paths.name_template = "{date}.mp4".to_owned();
+        assert!(config.validate().is_err());
+
+        let mut config = AppConfig::default();
+        config.retry.max_attempts = 0;
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config `retry`: max_attempts must be greater than 0"
+        );
+    }
+}
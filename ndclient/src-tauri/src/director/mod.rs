@@ -1,17 +1,34 @@
 use std::{io, path::PathBuf, process::Command, sync::Arc, time::Duration};
 
-use hound::WavReader;
-use nanoid::nanoid;
 use thiserror::Error;
-use tokio::fs;
+pub mod output;
 pub mod source;
 
 use crate::{
-    director::source::NewsSource,
-    news::{MaterialExtractor, NewsMaterial, NewsMaterialError, NewsMaterialResult, NewsTitle},
-    subtitle::{SingleSubtitle, Subtitle},
-    tts::{TTSFile, TTSService, get_wav_len},
-    video::VideoEditor,
+    audio::{
+        Audio,
+        assemble::{Assembled, Assembler, AssemblyConfig},
+        effects::{Effect, EffectChain},
+        fit::{self, FitClip, LengthConfig, LengthReport},
+        loudness::{self, LoudnessConfig, LoudnessReport},
+        music::{MusicBed, MusicConfig, MusicLibrary},
+        sfx::{Cue, SfxConfig, SfxEvent, SfxMixer},
+    },
+    director::{
+        output::{JobMetadata, VideoOutput},
+        source::NewsSource,
+    },
+    news::{
+        MaterialExtractor, NewsMaterial, NewsMaterialError, NewsMaterialResult, NewsTitle, Sentence,
+    },
+    subtitle::{Subtitle, SubtitleMode},
+    timeline::Timeline,
+    tts::{
+        TTSFile, TTSService,
+        align::{align_audio, align_even, scale_timings},
+    },
+    video::{VideoEditor, ffmpeg_error},
+    workspace::{DEFAULT_TEMP_DIR, Workspace},
 };
 
 #[derive(Error, Debug)]
@@ -33,20 +50,41 @@ pub enum DirectorError {
 pub type DirectorResult<T> = Result<T, DirectorError>;
 
 pub struct Director {
-    cur_id: String,
+    temp_dir: PathBuf,
+    output: VideoOutput,
+    keep_artifacts: bool,
+    voice_effects: EffectChain,
+    assembler: Assembler,
+    loudness: LoudnessConfig,
+    music: MusicConfig,
+    sfx: SfxConfig,
+    length: LengthConfig,
     source: NewsSource,
     tts: Option<Box<dyn TTSService + Sync + Send + 'static>>,
     subtitle: Option<Box<dyn Subtitle + Sync + Send + 'static>>,
+    subtitle_mode: SubtitleMode,
+    /// written beside the video, not into it
+    sidecars: Vec<Box<dyn Subtitle + Sync + Send + 'static>>,
     video_editor: Option<Box<dyn VideoEditor + Sync + Send + 'static>>,
 }
 
 impl Director {
     pub fn new(source: NewsSource) -> Self {
         Self {
-            cur_id: nanoid::nanoid!(10),
+            temp_dir: PathBuf::from(DEFAULT_TEMP_DIR),
+            output: VideoOutput::default(),
+            keep_artifacts: false,
+            voice_effects: EffectChain::default(),
+            assembler: Assembler::new(AssemblyConfig::default()),
+            loudness: LoudnessConfig::default(),
+            music: MusicConfig::default(),
+            sfx: SfxConfig::default(),
+            length: LengthConfig::default(),
             source,
             tts: None,
             subtitle: None,
+            subtitle_mode: SubtitleMode::default(),
+            sidecars: vec![],
             video_editor: None,
         }
     }
@@ -57,12 +95,60 @@ impl Director {
         self
     }
 
+    /// Effects on every TTS clip, none by default
+    pub fn with_voice_effects(mut self, voice_effects: EffectChain) -> Self {
+        self.voice_effects = voice_effects;
+        self
+    }
+
+    /// Format of the dubbing and the pauses between the sentences
+    pub fn with_assembly(mut self, config: AssemblyConfig) -> Self {
+        self.assembler = Assembler::new(config);
+        self
+    }
+
+    /// Loudness target of the dubbing
+    pub fn with_loudness(mut self, config: LoudnessConfig) -> Self {
+        self.loudness = config;
+        self
+    }
+
+    /// Background music under the dubbing, off unless enabled, then a random track of `./music`
+    pub fn with_music(mut self, config: MusicConfig) -> Self {
+        self.music = config;
+        self
+    }
+
+    /// Sound effects at the start, the picture changes and the emphasised sentences
+    pub fn with_sfx(mut self, config: SfxConfig) -> Self {
+        self.sfx = config;
+        self
+    }
+
+    /// Length to fit the video to, by the speech tempo and leaving sentences out
+    pub fn with_length(mut self, config: LengthConfig) -> Self {
+        self.length = config;
+        self
+    }
+
     pub fn with_subtitle(mut self, subtitle: impl Subtitle + Sync + Send + 'static) -> Self {
         let subtitle = Box::new(subtitle);
         self.subtitle = Some(subtitle);
         self
     }
 
+    /// Burn the subtitles in, or mux them as a track, burnt in by default
+    pub fn with_subtitle_mode(mut self, mode: SubtitleMode) -> Self {
+        self.subtitle_mode = mode;
+        self
+    }
+
+    /// Another subtitle file saved beside the video, like WebVTT captions
+    pub fn with_sidecar(mut self, subtitle: impl Subtitle + Sync + Send + 'static) -> Self {
+        self.sidecars.push(Box::new(subtitle));
+        self
+    }
+
     pub fn with_video_editor(
         mut self,
         video_editor: impl VideoEditor + Sync + Send + 'static,
@@ -72,12 +158,39 @@ impl Director {
         self
     }
 
+    /// Base directory of the job workspaces
+    pub fn with_temp_dir(mut self, temp_dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = temp_dir.into();
+        self
+    }
+
+    /// Where the final videos are stored and how they are named
+    pub fn with_output(mut self, output: VideoOutput) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Keep the job workspace and all intermediate files after shot, for debugging
+    pub fn with_keep_artifacts(mut self, keep: bool) -> Self {
+        self.keep_artifacts = keep;
+        self
+    }
+
     pub async fn get_hot_news_list(&self) -> Vec<NewsTitle> {
         self.source.crawler.get_hot_news_list().await
     }
 
     pub async fn shot_single(&mut self, news_title: &NewsTitle) -> DirectorResult<NewsShortVideo> {
-        self.cur_id = nanoid::nanoid!(10);
+        // the final video is always spoken and subtitled, fail before fetching anything
+        if self.tts.is_none() {
+            return Err(DirectorError::TTS("has no TTS setted".to_owned()));
+        }
+        if self.subtitle.is_none() {
+            return Err(DirectorError::Subtitle("has no subtitle setted".to_owned()));
+        }
+
+        // removed on drop, whatever the shot succeeds or not
+        let workspace = Workspace::create(&self.temp_dir)?.keep_artifacts(self.keep_artifacts);
 
         struct WrapExtractor {
             inner: Arc<Box<dyn MaterialExtractor + Sync + Send>>,
@@ -98,200 +211,303 @@ impl Director {
             news_title.get_news_material(&wrap_extractor).await?
         };
 
-        let dubbing_path = if self.tts.is_some() {
-            Some(self.gen_dubbing(&material).await?)
-        } else {
-            None
-        };
-
-        let subtitle_path = if let Some(ref subtitle_handler) = self.subtitle
-            && let Some(ref dubbing_subtitle) = dubbing_path
-        {
-            Some(
-                subtitle_handler
-                    .write_subtitle(&dubbing_subtitle.tts_files)
-                    .await
-                    .map_err(|e| DirectorError::Subtitle(e.to_string()))?,
-            )
-        } else {
-            None
-        };
+        let dubbing = self.gen_dubbing(&material, &workspace).await?;
 
-        let video_path = {
-            let dur = if let Some(ref dubbing) = dubbing_path {
-                let reader = WavReader::open(&dubbing.dubbing_path)
-                    .map_err(|e| DirectorError::TTS(e.to_string()))?;
+        // every later step takes its times from here
+        let timeline = dubbing.timeline.clone();
 
-                let seconds = reader.duration() as f64 / reader.spec().sample_rate as f64;
-                let duration = Duration::from_secs_f64(seconds);
+        let subtitle_path = match self.subtitle {
+            Some(ref subtitle_handler) => subtitle_handler
+                .write_subtitle(&timeline, &workspace)
+                .await
+                .map_err(|e| DirectorError::Subtitle(e.to_string()))?,
+            None => return Err(DirectorError::Subtitle("has no subtitle setted".to_owned())),
+        };
 
-                Some(duration)
-            } else {
-                None
-            };
+        let mut sidecar_paths = vec![];
+        if self.subtitle_mode == SubtitleMode::Soft {
+            sidecar_paths.push(subtitle_path.clone());
+        }
+        for sidecar in &self.sidecars {
+            let path = sidecar
+                .write_subtitle(&timeline, &workspace)
+                .await
+                .map_err(|e| DirectorError::Subtitle(e.to_string()))?;
+            sidecar_paths.push(path);
+        }
 
-            if self.video_editor.is_some() {
-                Some(self.gen_video(&material, dur).await?)
-            } else {
-                None
-            }
+        let video_path = if self.video_editor.is_some() {
+            Some(self.gen_video(&material, &timeline, &workspace).await?)
+        } else {
+            None
         };
 
         let video_path = video_path
             .ok_or_else(|| DirectorError::VideoEditor("has not video editor setted".to_string()))?;
 
-        let final_path = self
+        let composed_path = self
             .compose_all(
                 video_path,
-                dubbing_path.unwrap().dubbing_path,
-                subtitle_path.unwrap(),
+                dubbing.dubbing_path,
+                subtitle_path,
+                &timeline,
+                &workspace,
             )
             .await
             .map_err(|e| DirectorError::VideoEditor(e.to_string()))?;
 
+        let source = self.source.crawler.source_name();
+        let final_path = self
+            .output
+            .save(&composed_path, source, &material.title, workspace.id())
+            .await?;
+
+        let mut sidecars = vec![];
+        for path in &sidecar_paths {
+            sidecars.push(self.output.save_sidecar(&final_path, path).await?);
+        }
+
+        let metadata = JobMetadata {
+            id: workspace.id().to_owned(),
+            title: material.title.clone(),
+            source: source.to_owned(),
+            created_at: chrono::Local::now().to_rfc3339(),
+            loudness: dubbing.loudness,
+            length: dubbing.length,
+            music: dubbing.music,
+        };
+        let metadata_path = self.output.save_metadata(&final_path, &metadata).await?;
+
         Ok(NewsShortVideo {
             title: material.title.clone(),
             path: final_path,
+            sidecars,
+            metadata_path,
+            metadata,
         })
     }
 
-    async fn gen_dubbing(&mut self, material: &NewsMaterial) -> DirectorResult<DubbingSubtitle> {
+    async fn gen_dubbing(
+        &mut self,
+        material: &NewsMaterial,
+        workspace: &Workspace,
+    ) -> DirectorResult<Dubbing> {
         if self.tts.is_none() {
             return Err(DirectorError::TTS("has no TTS setted".to_owned()));
         }
 
+        // blank sentences have no audio, skipping them keeps the files matching the sentences
+        let sentences: Vec<Sentence> = material
+            .summary
+            .iter()
+            .filter(|sentence| !sentence.text.trim().is_empty())
+            .cloned()
+            .collect();
+
         let mut tts_files = self
             .tts
             .as_ref()
             .unwrap()
-            .tts(&material.summary)
+            .tts_sentences(&sentences, workspace)
             .await
             .map_err(|e| DirectorError::TTS(e.to_string()))?;
 
-        // carton tts files
         for tts_file in tts_files.iter_mut() {
-            let mut new_file_path = tts_file.path.clone();
-            new_file_path.set_file_name(format!(
-                "{}-cartoned.{}",
-                new_file_path.file_stem().unwrap().to_str().unwrap(),
-                new_file_path.extension().unwrap().to_str().unwrap()
-            ));
-
-            Command::new("ffmpeg")
-                .args(&[
-                    "-i",
-                    tts_file.path.to_str().unwrap(),
-                    "-af",
-                    "asetrate=30000, aresample=22050, atempo=1",
-                    new_file_path.to_str().unwrap(),
-                ])
-                .output()
-                .map_err(|e| DirectorError::TTS(e.to_string()))?;
-
-            _ = fs::remove_file(&tts_file.path).await;
+            if !self.voice_effects.is_empty() {
+                apply_effects(tts_file, &self.voice_effects, "effected")?;
+            }
 
-            tts_file.duration = get_wav_len(&new_file_path)
-                .await
-                .map_err(|e| DirectorError::TTS(e.to_string()))?;
-            tts_file.path = new_file_path;
+            if tts_file.words.is_none() {
+                tts_file.words = Some(
+                    align_audio(&tts_file.text, &tts_file.path)
+                        .unwrap_or_else(|_| align_even(&tts_file.text, tts_file.duration)),
+                );
+            }
         }
 
         // compose up
-        let compose_path = self.compose_audio(&tts_files).await?;
-
-        let subtitles: Vec<SingleSubtitle> = tts_files
-            .into_iter()
-            .map(|tts| {
-                _ = std::fs::remove_file(&tts.path);
-                return SingleSubtitle {
-                    text: tts.text.clone(),
-                    duration: tts.duration,
-                };
-            })
-            .collect();
-
-        Ok(DubbingSubtitle {
-            dubbing_path: compose_path,
-            tts_files: subtitles,
-        })
+        self.compose_audio(tts_files, sentences, workspace).await
     }
 
-    async fn compose_audio(&self, tts_files: &Vec<TTSFile>) -> DirectorResult<PathBuf> {
+    /// Join the clips into the dubbing, fit it to the target length, time the sentences on it,
+    /// mix the music and sound effects in it and normalise its loudness
+    async fn compose_audio(
+        &self,
+        mut tts_files: Vec<TTSFile>,
+        mut sentences: Vec<Sentence>,
+        workspace: &Workspace,
+    ) -> DirectorResult<Dubbing> {
         if tts_files.is_empty() {
             return Err(DirectorError::TTS("has no tts_files".to_owned()));
         }
 
-        let spec = {
-            let first = tts_files.get(0).unwrap();
-            let reader =
-                WavReader::open(&first.path).map_err(|e| DirectorError::TTS(e.to_string()))?;
-            reader.spec()
-        };
+        let mut assembled = self.assemble(&tts_files)?;
+
+        let length = if let Some(target) = self.length.target() {
+            let natural = assembled.audio.duration();
+            let plan = fit::plan(&self.length, target, &fit_clips(&assembled, &sentences));
+
+            let dropped = plan
+                .dropped
+                .iter()
+                .map(|&i| sentences[i].text.clone())
+                .collect();
+            if !plan.dropped.is_empty() {
+                let keep = |i: &usize| !plan.dropped.contains(i);
+                tts_files = (0..)
+                    .zip(tts_files)
+                    .filter(|(i, _)| keep(i))
+                    .map(|(_, f)| f)
+                    .collect();
+                sentences = (0..)
+                    .zip(sentences)
+                    .filter(|(i, _)| keep(i))
+                    .map(|(_, s)| s)
+                    .collect();
+            }
+            if plan.tempo != 1.0 {
+                let tempo = EffectChain(vec![Effect::Tempo {
+                    rate: plan.tempo as f32,
+                }]);
+                for tts_file in tts_files.iter_mut() {
+                    apply_effects(tts_file, &tempo, "fitted")?;
+                }
+            }
+            if plan.tempo != 1.0 || !plan.dropped.is_empty() {
+                assembled = self.assemble(&tts_files)?;
+            }
 
-        let silence = {
-            let silence_duration = 0.3;
-            let num_samples = (spec.sample_rate as f32 * silence_duration) as usize;
-            vec![0; num_samples * spec.channels as usize]
+            // only the pause after the last sentence, or a rounding of the stretching, may be cut
+            let fitted = assembled.audio.duration();
+            let speech_end = assembled
+                .offsets
+                .last()
+                .map(|o| o.end())
+                .unwrap_or_default();
+            if speech_end > target + FIT_TOLERANCE {
+                return Err(DirectorError::Failed(format!(
+                    "the speech takes {:.1}s at the fastest tempo, longer than the target of {:.1}s",
+                    speech_end.as_secs_f64(),
+                    target.as_secs_f64()
+                )));
+            }
+
+            // exactly, the rest is silence at the end
+            assembled.audio.set_duration(target);
+
+            Some(LengthReport {
+                natural_secs: natural.as_secs_f64(),
+                fitted_secs: fitted.as_secs_f64(),
+                target_secs: target.as_secs_f64(),
+                tempo: plan.tempo,
+                dropped,
+            })
+        } else {
+            None
         };
 
-        let mut compose_wav = vec![];
+        let duration = assembled.audio.duration();
+        let timeline = Timeline::from_dubbing(&sentences, tts_files, &assembled.offsets, duration);
 
-        for tts_file in tts_files {
-            let mut reader = WavReader::open(&tts_file.path).unwrap();
-            let samples: Vec<i32> = reader.samples::<i16>().map(|s| s.unwrap() as i32).collect();
+        let track = if self.music.enabled {
+            MusicLibrary::new(&self.music.dir).pick(&self.music.track)?
+        } else {
+            None
+        };
+        if let Some(ref track) = track {
+            assembled.audio = MusicBed::new(self.music.clone())
+                .mix_file(&assembled.audio, &timeline, track)
+                .map_err(|e| DirectorError::TTS(e.to_string()))?;
+        }
 
-            compose_wav.extend(samples.clone());
-            compose_wav.extend(silence.clone());
+        if self.sfx.enabled && !self.sfx.effects.is_empty() {
+            let cues = self.sfx_cues(&timeline);
+            SfxMixer::new(self.sfx.clone())
+                .mix(&mut assembled.audio, &cues)
+                .map_err(|e| DirectorError::TTS(e.to_string()))?;
         }
 
-        let final_wav = self
-            .get_temp_dir()
-            .await?
-            .join(format!("{}-final.wav", nanoid!()));
+        let report = self
+            .loudness
+            .enabled
+            .then(|| loudness::normalize(&mut assembled.audio, &self.loudness));
 
-        let mut writer = hound::WavWriter::create(&final_wav, spec)
+        let final_wav = workspace.unique_path("final.wav");
+        assembled
+            .audio
+            .write(&final_wav)
             .map_err(|e| DirectorError::TTS(e.to_string()))?;
 
-        for sample in compose_wav {
-            writer.write_sample(sample).unwrap();
+        Ok(Dubbing {
+            dubbing_path: final_wav,
+            timeline,
+            loudness: report,
+            length,
+            music: track.and_then(|track| Some(track.file_name()?.to_string_lossy().into_owned())),
+        })
+    }
+
+    fn assemble(&self, tts_files: &[TTSFile]) -> DirectorResult<Assembled> {
+        let clips: Vec<(&PathBuf, &str)> = tts_files
+            .iter()
+            .map(|tts_file| (&tts_file.path, tts_file.text.as_str()))
+            .collect();
+
+        self.assembler
+            .assemble(&clips)
+            .map_err(|e| DirectorError::TTS(e.to_string()))
+    }
+
+    /// The events of the video along `timeline`
+    fn sfx_cues(&self, timeline: &Timeline) -> Vec<Cue> {
+        let mut cues = vec![Cue::new(SfxEvent::VideoStart, Duration::ZERO)];
+
+        if let Some(ref video_editor) = self.video_editor {
+            cues.extend(
+                video_editor
+                    .transitions(timeline)
+                    .into_iter()
+                    .map(|at| Cue::new(SfxEvent::Transition, at)),
+            );
         }
 
-        Ok(final_wav)
+        cues.extend(
+            timeline
+                .emphasis()
+                .map(|at| Cue::new(SfxEvent::Emphasis, at)),
+        );
+
+        cues
     }
 
+    /// Mux the video and the dubbing with the subtitles,
+    /// burnt in (SRT or styled ASS alike) or as a `mov_text` track
     async fn compose_all(
         &self,
         video: PathBuf,
         dubbing: PathBuf,
         subtitle: PathBuf,
+        timeline: &Timeline,
+        workspace: &Workspace,
     ) -> DirectorResult<PathBuf> {
-        let output_path = self
-            .get_temp_dir()
-            .await?
-            .join(format!("{}-final.mp4", nanoid::nanoid!()));
-
-        Command::new("ffmpeg")
-            .args(&[
-                "-i",
-                video.to_str().unwrap(),
-                "-i",
-                dubbing.to_str().unwrap(),
-                "-vf",
-                &format!("subtitles='{}'", subtitle.to_str().unwrap()),
-                "-c:v",
-                "libx264",
-                "-c:a",
-                "copy",
-                "-shortest",
-                "-y",
-                output_path.to_str().unwrap(),
-            ])
+        let output_path = workspace.unique_path("final.mp4");
+        let args = compose_args(
+            self.subtitle_mode,
+            [&video, &dubbing, &subtitle, &output_path],
+            timeline.duration,
+        );
+
+        let output = Command::new("ffmpeg")
+            .args(&args)
             .output()
             .map_err(|e| DirectorError::VideoEditor(e.to_string()))?;
-
-        _ = fs::remove_file(video).await;
-        _ = fs::remove_file(dubbing).await;
-        _ = fs::remove_file(subtitle).await;
+        if !output.status.success() {
+            return Err(DirectorError::VideoEditor(format!(
+                "ffmpeg failed with {}: {}",
+                output.status,
+                ffmpeg_error(&output.stderr)
+            )));
+        }
 
         Ok(output_path)
     }
@@ -299,7 +515,8 @@ impl Director {
     async fn gen_video(
         &self,
         material: &NewsMaterial,
-        dur: Option<Duration>,
+        timeline: &Timeline,
+        workspace: &Workspace,
     ) -> DirectorResult<PathBuf> {
         if self.video_editor.is_none() {
             return Err(DirectorError::VideoEditor(
@@ -307,78 +524,179 @@ impl Director {
             ));
         }
 
-        let time = if let Some(time) = dur {
-            time
-        } else {
-            Duration::from_secs((material.pics.len() * 2) as u64)
-        };
-
         self.video_editor
             .as_ref()
             .unwrap()
-            .do_edit(material, time)
+            .do_edit(material, timeline, workspace)
             .await
             .map_err(|e| DirectorError::VideoEditor(e.to_string()))
     }
+}
 
-    async fn get_temp_dir(&self) -> DirectorResult<PathBuf> {
-        let temp = PathBuf::from(format!("./temp/{}", self.cur_id.to_string()));
-        if !temp.exists() {
-            fs::create_dir_all(&temp).await.unwrap();
-        }
-
-        let temp = temp.canonicalize().unwrap();
+/// Arguments of ffmpeg composing `[video, dubbing, subtitle, output]` of `duration`
+fn compose_args(mode: SubtitleMode, paths: [&PathBuf; 4], duration: Duration) -> Vec<String> {
+    let [video, dubbing, subtitle, output] = paths.map(|path| path.to_str().unwrap().to_owned());
+
+    let mut args = vec!["-i".to_owned(), video, "-i".to_owned(), dubbing];
+    let mode_args = match mode {
+        SubtitleMode::Burn => vec![
+            "-vf".to_owned(),
+            format!("subtitles='{subtitle}'"),
+            "-c:v".to_owned(),
+            "libx264".to_owned(),
+        ],
+        // the picture is kept as edited
+        SubtitleMode::Soft => [
+            "-i",
+            &subtitle,
+            "-map",
+            "0:v",
+            "-map",
+            "1:a",
+            "-map",
+            "2:s",
+            "-c:v",
+            "copy",
+            "-c:s",
+            "mov_text",
+            "-metadata:s:s:0",
+            "language=chi",
+        ]
+        .map(str::to_owned)
+        .to_vec(),
+    };
+    args.extend(mode_args);
+
+    // MP4 does not take the PCM of the dubbing
+    args.extend(
+        [
+            "-c:a",
+            "aac",
+            "-b:a",
+            "192k",
+            "-t",
+            &format!("{:.3}", duration.as_secs_f64()),
+            "-shortest",
+            "-y",
+            &output,
+        ]
+        .map(str::to_owned),
+    );
+
+    args
+}
 
-        return Ok(temp);
+/// How much of the last sentence may be cut when fitting to a target length
+const FIT_TOLERANCE: Duration = Duration::from_millis(50);
+
+/// Apply `chain` to a clip, written beside it with `suffix`
+fn apply_effects(tts_file: &mut TTSFile, chain: &EffectChain, suffix: &str) -> DirectorResult<()> {
+    let audio = Audio::read(&tts_file.path).map_err(|e| DirectorError::TTS(e.to_string()))?;
+    let audio = chain.apply(audio);
+
+    let mut new_file_path = tts_file.path.clone();
+    new_file_path.set_file_name(format!(
+        "{}-{suffix}.wav",
+        new_file_path.file_stem().unwrap().to_str().unwrap(),
+    ));
+    audio
+        .write(&new_file_path)
+        .map_err(|e| DirectorError::TTS(e.to_string()))?;
+
+    // an effect may change the speed, the word timings follow it
+    if let Some(words) = tts_file.words.as_mut() {
+        scale_timings(words, tts_file.duration, audio.duration());
     }
+    tts_file.duration = audio.duration();
+    tts_file.path = new_file_path;
+
+    Ok(())
+}
+
+/// The assembled clips with the pause after each and the priority of their sentences
+fn fit_clips(assembled: &Assembled, sentences: &[Sentence]) -> Vec<FitClip> {
+    let ends = assembled
+        .offsets
+        .iter()
+        .skip(1)
+        .map(|next| next.start)
+        .chain([assembled.audio.duration()]);
+
+    assembled
+        .offsets
+        .iter()
+        .zip(ends)
+        .zip(sentences)
+        .map(|((offset, next), sentence)| FitClip {
+            duration: offset.duration,
+            pause: next.saturating_sub(offset.end()),
+            priority: sentence.priority,
+            emphasis: sentence.emphasis,
+        })
+        .collect()
 }
 
-struct DubbingSubtitle {
+struct Dubbing {
     dubbing_path: PathBuf,
-    tts_files: Vec<SingleSubtitle>,
+    /// when every sentence is spoken in it
+    timeline: Timeline,
+    loudness: Option<LoudnessReport>,
+    /// how it was fit to the target length
+    length: Option<LengthReport>,
+    /// file name of the music track
+    music: Option<String>,
 }
 
 pub struct NewsShortVideo {
     pub title: String,
     pub path: PathBuf,
+    /// subtitle files beside the video
+    pub sidecars: Vec<PathBuf>,
+    /// the JSON file of `metadata`, beside the video
+    pub metadata_path: PathBuf,
+    pub metadata: JobMetadata,
 }
 
 #[cfg(test)]
 mod tests {
     use reqwest::header;
+    use tokio::fs;
 
     use crate::{
-        news::NewsCrawler, subtitle::srt::SrtSubtitle, tts::ali_tts::AliTTS,
+        news::NewsCrawler,
+        subtitle::srt::SrtSubtitle,
+        tts::{ali_tts::AliTTS, synthetic::SyntheticTTS},
         video::junior_editor::JuniorEditor,
     };
 
     use super::*;
 
-    #[tokio::test]
-    async fn dubbing_tts_none() {
-        struct MockCrawler;
+    /// A source never asked, for the tests not crawling anything
+    struct MockCrawler;
 
-        #[async_trait::async_trait]
-        impl NewsCrawler for MockCrawler {
-            async fn get_hot_news_list(&self) -> Vec<NewsTitle> {
-                unimplemented!()
-            }
+    #[async_trait::async_trait]
+    impl NewsCrawler for MockCrawler {
+        async fn get_hot_news_list(&self) -> Vec<NewsTitle> {
+            unimplemented!()
         }
+    }
 
-        struct MockMaterialExtractor;
+    struct MockMaterialExtractor;
 
-        #[async_trait::async_trait]
-        impl MaterialExtractor for MockMaterialExtractor {
-            async fn get_material(&self, _hot_news: &NewsTitle) -> NewsMaterialResult {
-                unimplemented!()
-            }
+    #[async_trait::async_trait]
+    impl MaterialExtractor for MockMaterialExtractor {
+        async fn get_material(&self, _hot_news: &NewsTitle) -> NewsMaterialResult {
+            unimplemented!()
         }
+    }
 
+    #[tokio::test]
+    async fn dubbing_tts_none() {
         let material = NewsMaterial {
             title: "这是一个标题".to_owned(),
             summary: vec![
-                "闺蜜闺蜜想不想玩第五人格喵喵喵".to_owned(),
-                "兄弟兄弟想不想玩第五人格喵喵喵".to_owned(),
+                Sentence::new("闺蜜闺蜜想不想玩第五人格喵喵喵"),
+                Sentence::new("兄弟兄弟想不想玩第五人格喵喵喵"),
             ],
             pics: vec![
                 "./tests/mock_pic_1.jpeg".to_owned(),
@@ -392,11 +710,218 @@ mod tests {
             extractor: MockMaterialExtractor.into(),
         });
 
-        let path = director.gen_dubbing(&material).await;
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = director.gen_dubbing(&material, &workspace).await;
 
         assert!(path.is_err());
     }
 
+    #[tokio::test]
+    async fn shot_without_tts_or_subtitle() {
+        let news_title = NewsTitle {
+            title: "这是一个标题".to_owned(),
+            url: "https://example.com".to_owned(),
+            pics: vec![],
+            videos: vec![],
+        };
+
+        let mut director = Director::new(NewsSource {
+            crawler: Box::new(MockCrawler),
+            extractor: MockMaterialExtractor.into(),
+        })
+        .with_video_editor(JuniorEditor::new());
+        let result = director.shot_single(&news_title).await;
+        assert!(matches!(result, Err(DirectorError::TTS(_))));
+
+        let mut director = director.with_tts(SyntheticTTS::new());
+        let result = director.shot_single(&news_title).await;
+        assert!(matches!(result, Err(DirectorError::Subtitle(_))));
+    }
+
+    #[tokio::test]
+    async fn subtitles_on_the_audio_offsets() {
+        let texts = [
+            "第五人格启动，",
+            "闺蜜闺蜜想不想玩！",
+            "兄弟兄弟想不想玩",
+            "喵喵喵。",
+        ];
+        let material = NewsMaterial {
+            title: "这是一个标题".to_owned(),
+            summary: texts.iter().map(|text| Sentence::new(*text)).collect(),
+            pics: vec![],
+            videos: vec![],
+        };
+
+        let tts = SyntheticTTS::new().with_tone(440.0);
+        let durations: Vec<Duration> = texts.iter().map(|text| tts.duration_of(text)).collect();
+        let mut director = Director::new(NewsSource {
+            crawler: Box::new(MockCrawler),
+            extractor: MockMaterialExtractor.into(),
+        })
+        .with_tts(tts)
+        .with_music(MusicConfig {
+            enabled: false,
+            ..MusicConfig::default()
+        });
+
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let dubbing = director.gen_dubbing(&material, &workspace).await.unwrap();
+        let timeline = &dubbing.timeline;
+
+        // the clips one after another, with the pause of their punctuation
+        let assembly = AssemblyConfig::default();
+        let mut start = Duration::ZERO;
+        for ((segment, text), duration) in timeline.segments.iter().zip(texts).zip(&durations) {
+            assert_eq!(segment.start, start);
+            assert_eq!(segment.duration, *duration);
+            start += *duration + assembly.pause_after(text);
+        }
+        assert_eq!(timeline.duration, start);
+
+        let audio = Audio::read(&dubbing.dubbing_path).unwrap();
+        assert_eq!(audio.duration(), timeline.duration);
+        let rms = |from: Duration| {
+            let from = (from.as_secs_f64() * audio.sample_rate as f64) as usize;
+            let window = &audio.samples[from..from + 160];
+            (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).sqrt()
+        };
+
+        let srt = SrtSubtitle::new()
+            .write_subtitle(timeline, &workspace)
+            .await
+            .unwrap();
+        let srt = std::fs::read_to_string(srt).unwrap();
+        let cue_starts: Vec<&str> = srt
+            .lines()
+            .filter_map(|line| line.split_once(" --> "))
+            .map(|(start, _)| start)
+            .collect();
+
+        assert_eq!(cue_starts.len(), texts.len());
+        for (cue_start, segment) in cue_starts.iter().zip(&timeline.segments) {
+            let millis = segment.start.as_millis();
+            assert_eq!(
+                *cue_start,
+                format!("00:00:{:02},{:03}", millis / 1000, millis % 1000)
+            );
+            // speech right at the cue, the pause before it is silent
+            assert!(rms(segment.start) > 0.01);
+            if !segment.start.is_zero() {
+                assert_eq!(rms(segment.start - Duration::from_millis(50)), 0.0);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fit_to_target_length() {
+        // 1s of speech and a 450 ms pause each, 5.8s in all
+        let material = NewsMaterial {
+            title: "这是一个标题".to_owned(),
+            summary: vec![
+                Sentence::new("第五人格。").with_priority(2),
+                Sentence::new("闺蜜想玩。").with_priority(1),
+                Sentence::new("兄弟想玩。").with_priority(3),
+                Sentence::new("喵喵喵喵。").with_priority(2),
+            ],
+            pics: vec![],
+            videos: vec![],
+        };
+
+        let mut director = Director::new(NewsSource {
+            crawler: Box::new(MockCrawler),
+            extractor: MockMaterialExtractor.into(),
+        })
+        .with_tts(SyntheticTTS::new().with_tone(440.0))
+        .with_music(MusicConfig {
+            enabled: false,
+            ..MusicConfig::default()
+        })
+        .with_length(LengthConfig {
+            target_secs: 4.0,
+            ..LengthConfig::default()
+        });
+
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let dubbing = director.gen_dubbing(&material, &workspace).await.unwrap();
+
+        // too long even at the fastest tempo, the least important sentence goes
+        let length = dubbing.length.unwrap();
+        assert_eq!(length.natural_secs, 5.8);
+        assert!((length.fitted_secs - 4.0).abs() < 0.05);
+        assert_eq!(length.dropped, ["闺蜜想玩。"]);
+        assert!((length.tempo - 3.0 / 2.65).abs() < 1e-9);
+
+        let target = Duration::from_secs(4);
+        let timeline = &dubbing.timeline;
+        assert_eq!(timeline.duration, target);
+        assert_eq!(
+            Audio::read(&dubbing.dubbing_path).unwrap().duration(),
+            target
+        );
+        assert_eq!(timeline.segments.len(), 3);
+        assert_eq!(timeline.segments[1].text, "兄弟想玩。");
+        for segment in &timeline.segments {
+            let expected = 1.0 / length.tempo;
+            assert!((segment.duration.as_secs_f64() - expected).abs() < 0.02);
+        }
+        assert!(timeline.segments[2].end() < target);
+    }
+
+    #[tokio::test]
+    async fn hook_longer_than_target() {
+        // the hook is 1.8s, 1.5s at the fastest tempo
+        let material = NewsMaterial {
+            title: "这是一个标题".to_owned(),
+            summary: vec![
+                Sentence::new("第五人格第五人格。").with_priority(3),
+                Sentence::new("喵喵喵。").with_priority(1),
+            ],
+            pics: vec![],
+            videos: vec![],
+        };
+
+        let mut director = Director::new(NewsSource {
+            crawler: Box::new(MockCrawler),
+            extractor: MockMaterialExtractor.into(),
+        })
+        .with_tts(SyntheticTTS::new().with_tone(440.0))
+        .with_length(LengthConfig {
+            target_secs: 1.0,
+            ..LengthConfig::default()
+        });
+
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let result = director.gen_dubbing(&material, &workspace).await;
+
+        let Err(DirectorError::Failed(message)) = result else {
+            panic!("the hook must not be cut");
+        };
+        assert!(message.contains("longer than the target of 1.0s"));
+    }
+
+    #[test]
+    fn compose_args_by_mode() {
+        let paths = ["v.mp4", "d.wav", "s.srt", "final.mp4"].map(PathBuf::from);
+        let paths = [&paths[0], &paths[1], &paths[2], &paths[3]];
+        let duration = Duration::from_millis(12_345);
+
+        let burn = compose_args(SubtitleMode::Burn, paths, duration).join(" ");
+        assert_eq!(
+            burn,
+            "-i v.mp4 -i d.wav -vf subtitles='s.srt' -c:v libx264 \
+             -c:a aac -b:a 192k -t 12.345 -shortest -y final.mp4"
+        );
+
+        let soft = compose_args(SubtitleMode::Soft, paths, duration).join(" ");
+        assert_eq!(
+            soft,
+            "-i v.mp4 -i d.wav -i s.srt -map 0:v -map 1:a -map 2:s -c:v copy \
+             -c:s mov_text -metadata:s:s:0 language=chi \
+             -c:a aac -b:a 192k -t 12.345 -shortest -y final.mp4"
+        );
+    }
+
     #[tokio::test]
     async fn director_shot_single() {
         struct MockCrawler;
@@ -441,7 +966,7 @@ mod tests {
 
         // Create a mock
         let _mock = server
-            .mock("GET", "/first")
+            .mock("POST", "/first")
             .with_status(200)
             .with_header(header::CONTENT_TYPE, "application/json")
             .with_body(first_body)
@@ -465,8 +990,8 @@ mod tests {
                 Ok(NewsMaterial {
                     title: "这是一个标题".to_owned(),
                     summary: vec![
-                        "闺蜜闺蜜想不想玩第五人格喵喵喵".to_owned(),
-                        "兄弟兄弟想不想玩第五人格喵喵喵".to_owned(),
+                        Sentence::new("闺蜜闺蜜想不想玩第五人格喵喵喵"),
+                        Sentence::new("兄弟兄弟想不想玩第五人格喵喵喵"),
                     ],
                     pics: vec![
                         format!("{}/mock_pic_1", url.clone()),
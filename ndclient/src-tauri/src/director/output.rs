@@ -0,0 +1,304 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tokio::fs;
+
+use crate::audio::{fit::LengthReport, loudness::LoudnessReport};
+
+pub const DEFAULT_OUTPUT_DIR: &str = "./output";
+pub const DEFAULT_NAME_TEMPLATE: &str = "{date}_{source}_{slug}.mp4";
+
+const MAX_SLUG_CHARS: usize = 40;
+
+/// Where the final videos are stored and how they are named.
+///
+/// The template supports `{date}`, `{source}`, `{slug}` and `{id}` placeholders.
+#[derive(Clone, Debug)]
+pub struct VideoOutput {
+    dir: PathBuf,
+    template: String,
+}
+
+impl VideoOutput {
+    pub fn new(dir: impl Into<PathBuf>, template: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into(),
+            template: template.into(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Render the file name of a video, without collision handling
+    pub fn file_name(&self, date: &str, source: &str, title: &str, id: &str) -> String {
+        let name = self
+            .template
+            .replace("{date}", date)
+            .replace("{source}", &slugify(source))
+            .replace("{slug}", &slugify(title))
+            .replace("{id}", id);
+
+        if Path::new(&name).extension().is_none() {
+            format!("{name}.mp4")
+        } else {
+            name
+        }
+    }
+
+    /// Move `composed` into the output directory, named after the template.
+    /// An existing file is never overwritten, a `-1`, `-2`... suffix is appended instead.
+    pub async fn save(
+        &self,
+        composed: &Path,
+        source: &str,
+        title: &str,
+        id: &str,
+    ) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir).await?;
+        let dir = self.dir.canonicalize()?;
+
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let file_name = self.file_name(&date, source, title, id);
+        let output_path = Self::reserve_path(&dir.join(file_name)).await?;
+
+        // the reserved empty file is replaced
+        if fs::rename(composed, &output_path).await.is_err() {
+            // rename fails across file systems
+            if let Err(e) = fs::copy(composed, &output_path).await {
+                _ = fs::remove_file(&output_path).await;
+                return Err(e);
+            }
+            _ = fs::remove_file(composed).await;
+        }
+
+        Ok(output_path)
+    }
+
+    /// Save the metadata of a job beside its video, as `<video stem>.json`
+    pub async fn save_metadata(&self, video: &Path, metadata: &JobMetadata) -> io::Result<PathBuf> {
+        let path = video.with_extension("json");
+        fs::write(&path, serde_json::to_vec_pretty(metadata)?).await?;
+
+        Ok(path)
+    }
+
+    /// Copy a subtitle file beside its video, as `<video stem>.<subtitle extension>`.
+    /// An existing file is never overwritten, like in [`Self::save`].
+    pub async fn save_sidecar(&self, video: &Path, subtitle: &Path) -> io::Result<PathBuf> {
+        let path =
+            Self::reserve_path(&video.with_extension(subtitle.extension().unwrap_or_default()))
+                .await?;
+        if let Err(e) = fs::copy(subtitle, &path).await {
+            _ = fs::remove_file(&path).await;
+            return Err(e);
+        }
+
+        Ok(path)
+    }
+
+    /// Create `path` empty, or the first free of its `-1`, `-2`... names.
+    /// Creating is atomic, so concurrent jobs never take the same name.
+    async fn reserve_path(path: &Path) -> io::Result<PathBuf> {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = path.extension().unwrap_or_default().to_string_lossy();
+
+        let mut candidate = path.to_path_buf();
+        let mut i = 0;
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&candidate)
+                .await
+            {
+                Ok(_) => return Ok(candidate),
+                Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+                Err(_) => {}
+            }
+
+            i += 1;
+            candidate = path.with_file_name(format!("{stem}-{i}.{ext}"));
+        }
+    }
+}
+
+impl Default for VideoOutput {
+    fn default() -> Self {
+        Self::new(DEFAULT_OUTPUT_DIR, DEFAULT_NAME_TEMPLATE)
+    }
+}
+
+/// What is known about a job and the video it made
+#[derive(Clone, Debug, Serialize)]
+pub struct JobMetadata {
+    pub id: String,
+    pub title: String,
+    pub source: String,
+    /// RFC 3339
+    pub created_at: String,
+    /// measured on the dubbing, `None` when not normalised
+    pub loudness: Option<LoudnessReport>,
+    /// `None` when kept at its natural length
+    pub length: Option<LengthReport>,
+    /// file name of the background music
+    pub music: Option<String>,
+}
+
+/// File name safe slug of a title.
+/// Chinese characters are kept as is, punctuation (full width as well) and
+/// whitespace become `-`, the result is limited to 40 characters.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug: String = slug
+        .trim_matches('-')
+        .chars()
+        .take(MAX_SLUG_CHARS)
+        .collect();
+    let slug = slug.trim_end_matches('-');
+
+    if slug.is_empty() {
+        "untitled".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::{DEFAULT_TEMP_DIR, Workspace};
+
+    #[test]
+    fn slugify_chinese_title() {
+        assert_eq!(
+            slugify("“第五人格”启动！闺蜜/兄弟: 要不要一起玩？"),
+            "第五人格-启动-闺蜜-兄弟-要不要一起玩"
+        );
+        assert_eq!(slugify("Hello World 2025"), "hello-world-2025");
+        assert_eq!(slugify("？！。"), "untitled");
+        assert_eq!(slugify(&"长".repeat(100)).chars().count(), MAX_SLUG_CHARS);
+    }
+
+    #[test]
+    fn render_file_name() {
+        let output = VideoOutput::default();
+
+        assert_eq!(
+            output.file_name("2025-07-03", "pengpai", "第五人格 启动", "abc"),
+            "2025-07-03_pengpai_第五人格-启动.mp4"
+        );
+
+        let output = VideoOutput::new(DEFAULT_OUTPUT_DIR, "{source}-{id}");
+        assert_eq!(
+            output.file_name("2025-07-03", "pengpai", "第五人格", "abc"),
+            "pengpai-abc.mp4"
+        );
+    }
+
+    #[tokio::test]
+    async fn save_without_overwrite() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let output = VideoOutput::new(workspace.path("output"), "{slug}.mp4");
+
+        let first = workspace.path("first.mp4");
+        fs::write(&first, "first").await.unwrap();
+        let first = output.save(&first, "test", "标题", "1").await.unwrap();
+
+        let second = workspace.path("second.mp4");
+        fs::write(&second, "second").await.unwrap();
+        let second = output.save(&second, "test", "标题", "2").await.unwrap();
+
+        assert_eq!(first.file_name().unwrap(), "标题.mp4");
+        assert_eq!(second.file_name().unwrap(), "标题-1.mp4");
+        assert_eq!(fs::read_to_string(first).await.unwrap(), "first");
+        assert_eq!(fs::read_to_string(second).await.unwrap(), "second");
+    }
+
+    #[tokio::test]
+    async fn metadata_beside_video() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let output = VideoOutput::new(workspace.path("output"), "{slug}.mp4");
+        let video = workspace.path("标题.mp4");
+
+        let metadata = JobMetadata {
+            id: "1".to_owned(),
+            title: "标题".to_owned(),
+            source: "test".to_owned(),
+            created_at: "2025-07-03T12:00:00+08:00".to_owned(),
+            loudness: Some(LoudnessReport {
+                input_lufs: Some(-25.5),
+                input_true_peak_db: -6.0,
+                output_lufs: Some(-16.0),
+                output_true_peak_db: -1.0,
+                gain_db: 9.5,
+            }),
+            length: None,
+            music: Some("calm.wav".to_owned()),
+        };
+        let path = output.save_metadata(&video, &metadata).await.unwrap();
+
+        assert_eq!(path, workspace.path("标题.json"));
+        let json: serde_json::Value =
+            serde_json::from_slice(&fs::read(&path).await.unwrap()).unwrap();
+        assert_eq!(json["loudness"]["output_lufs"], -16.0);
+        assert_eq!(json["loudness"]["gain_db"], 9.5);
+        assert_eq!(json["music"], "calm.wav");
+    }
+
+    #[tokio::test]
+    async fn sidecar_beside_video() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let output = VideoOutput::default();
+        let video = workspace.path("标题.mp4");
+        let subtitle = workspace.path("subtitle-1.vtt");
+        fs::write(&subtitle, "WEBVTT\n\n").await.unwrap();
+
+        let path = output.save_sidecar(&video, &subtitle).await.unwrap();
+
+        assert_eq!(path, workspace.path("标题.vtt"));
+        assert_eq!(fs::read_to_string(&path).await.unwrap(), "WEBVTT\n\n");
+
+        // the captions of another video are kept
+        let path = output.save_sidecar(&video, &subtitle).await.unwrap();
+        assert_eq!(path, workspace.path("标题-1.vtt"));
+    }
+
+    #[tokio::test]
+    async fn concurrent_saves_never_share_a_name() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let output = VideoOutput::new(workspace.path("output"), "{slug}.mp4");
+
+        let first = workspace.path("first.mp4");
+        let second = workspace.path("second.mp4");
+        fs::write(&first, "first").await.unwrap();
+        fs::write(&second, "second").await.unwrap();
+
+        let (first, second) = tokio::join!(
+            output.save(&first, "test", "标题", "1"),
+            output.save(&second, "test", "标题", "2")
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+
+        assert_ne!(first, second);
+        let mut contents = [
+            fs::read_to_string(first).await.unwrap(),
+            fs::read_to_string(second).await.unwrap(),
+        ];
+        contents.sort();
+        assert_eq!(contents, ["first", "second"]);
+    }
+}
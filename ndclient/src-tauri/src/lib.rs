@@ -1,12 +1,90 @@
+use std::{path::PathBuf, sync::RwLock};
+
+use openai::Credentials;
+use reqwest::Client;
+use serde::Serialize;
+use tauri::{Manager, State};
+use tokio::sync::Semaphore;
+
 use crate::{
-    director::{source::NewsSource, Director}, news::{pengpai_news::{PengPaiNews, PengPaiNewsMaterialExtractor}, NewsTitle}, subtitle::srt::SrtSubtitle, tts::ali_tts::AliTTS, video::junior_editor::JuniorEditor
+    audio::{
+        effects::{self, EffectChain, EffectPreset},
+        music::MusicLibrary,
+    },
+    config::{AppConfig, CONFIG_FILE_NAME, SubtitleFormat, TtsProvider},
+    director::{Director, output::VideoOutput, source::NewsSource},
+    news::{
+        NewsCrawler, NewsTitle,
+        pengpai_news::{PengPaiNews, PengPaiNewsMaterialExtractor},
+    },
+    secrets::{
+        ApiKeyKind, DASHSCOPE_BASE_URL, KEYFILE_NAME, KeySource, SECRETS_FILE_NAME, SecretStore,
+        check_api_key,
+    },
+    subtitle::{
+        SubtitleMode,
+        ass::{self, AssStyle, AssSubtitle, StylePreset},
+        srt::SrtSubtitle,
+        vtt::VttSubtitle,
+    },
+    tts::{
+        TTSService,
+        ali_tts::{AliTTS, AliTTSOptions, AliVoice, VOICES},
+        cache::CachedTTS,
+        cast::CastTTS,
+        http_tts_ser::HttpTTSSer,
+        local_tts::LocalTTS,
+        normalize::{Lexicon, NormalizedTTS, Normalizer},
+    },
+    video::junior_editor::JuniorEditor,
 };
 
+pub mod audio;
+pub mod config;
 pub mod director;
 pub mod news;
+pub mod retry;
+pub mod secrets;
 pub mod subtitle;
+pub mod timeline;
 pub mod tts;
 pub mod video;
+pub mod workspace;
+
+/// The loaded config and where it is stored
+struct ConfigState {
+    path: PathBuf,
+    config: RwLock<AppConfig>,
+}
+
+impl ConfigState {
+    fn get(&self) -> AppConfig {
+        self.config.read().unwrap().clone()
+    }
+}
+
+struct SecretsState(SecretStore);
+
+impl SecretsState {
+    /// Stringify an error with every API key masked
+    fn error(&self, e: impl ToString) -> String {
+        self.0.redact(&e.to_string())
+    }
+}
+
+#[derive(Serialize)]
+struct ApiKeyStatus {
+    kind: ApiKeyKind,
+    /// masked key, `None` if not set
+    masked: Option<String>,
+}
+
+/// Limits how many videos are generated at the same time,
+/// sized by `concurrency.max_jobs` when the app starts
+struct JobLimiter(Semaphore);
+
+/// One HTTP client for every job, so the connections are reused
+struct HttpState(Client);
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -15,44 +93,345 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-async fn get_hot_news() -> Vec<NewsTitle> {
-    let director = director::Director::new(NewsSource {
-        crawler: Box::new(news::pengpai_news::PengPaiNews::new()),
-        extractor: PengPaiNewsMaterialExtractor::from_deepseek().into(),
-    });
+fn get_config(state: State<'_, ConfigState>) -> AppConfig {
+    state.get()
+}
 
-    director.get_hot_news_list().await
+/// Validate and save the config, it takes effect for the next video
+#[tauri::command]
+fn update_config(state: State<'_, ConfigState>, config: AppConfig) -> Result<AppConfig, String> {
+    config.validate().map_err(|e| e.to_string())?;
+    config.save(&state.path).map_err(|e| e.to_string())?;
+
+    *state.config.write().unwrap() = config.clone();
+
+    Ok(config)
 }
 
 #[tauri::command]
-async fn gen_video(news_title: NewsTitle) -> String {
-    
-    let tts_url = dotenv::var("TTS_URL").unwrap();
-    let ali_key = dotenv::var("ALI_DASHSCOPE_API_KEY").unwrap();
+fn get_api_keys(secrets: State<'_, SecretsState>) -> Result<Vec<ApiKeyStatus>, String> {
+    ApiKeyKind::ALL
+        .iter()
+        .map(|kind| {
+            let masked = secrets
+                .0
+                .get(*kind)
+                .map_err(|e| secrets.error(e))?
+                .map(|secret| secret.masked());
+            Ok(ApiKeyStatus {
+                kind: *kind,
+                masked,
+            })
+        })
+        .collect()
+}
 
-    let mut director = Director::new(NewsSource {
-        crawler: Box::new(PengPaiNews::new()),
-        extractor: PengPaiNewsMaterialExtractor::from_deepseek().into(),
-    })
-    .with_tts(AliTTS::new(tts_url, ali_key))
-    .with_subtitle(SrtSubtitle::new())
-    .with_video_editor(JuniorEditor::new());
+#[tauri::command]
+fn set_api_key(
+    secrets: State<'_, SecretsState>,
+    kind: ApiKeyKind,
+    key: String,
+) -> Result<(), String> {
+    secrets.0.set(kind, &key).map_err(|e| secrets.error(e))
+}
+
+#[tauri::command]
+fn clear_api_key(secrets: State<'_, SecretsState>, kind: ApiKeyKind) -> Result<(), String> {
+    secrets.0.clear(kind).map_err(|e| secrets.error(e))
+}
+
+/// Check the stored key is accepted by its provider
+#[tauri::command]
+async fn test_api_key(
+    state: State<'_, ConfigState>,
+    secrets: State<'_, SecretsState>,
+    kind: ApiKeyKind,
+) -> Result<(), String> {
+    let config = state.get();
+    let key = secrets.0.resolve(kind).map_err(|e| secrets.error(e))?;
+
+    let url = match kind {
+        ApiKeyKind::DeepSeek => config.llm.base_url.as_str(),
+        ApiKeyKind::DashScope if config.tts.provider != TtsProvider::Ali => {
+            return Err(format!(
+                "the DashScope API key is not used by tts provider {:?}",
+                config.tts.provider
+            ));
+        }
+        ApiKeyKind::DashScope => DASHSCOPE_BASE_URL,
+    };
 
+    check_api_key(kind, &key, url)
+        .await
+        .map_err(|e| secrets.error(e))
+}
+
+/// Voices can be picked for a video
+#[tauri::command]
+fn list_voices() -> Vec<AliVoice> {
+    VOICES.to_vec()
+}
+
+/// Voice effects can be picked for a video
+#[tauri::command]
+fn list_voice_effects() -> Vec<EffectPreset> {
+    effects::presets()
+}
 
-    director
+/// Music tracks can be picked for a video
+#[tauri::command]
+fn list_music(state: State<'_, ConfigState>) -> Result<Vec<String>, String> {
+    MusicLibrary::new(state.get().music.dir)
+        .tracks()
+        .map_err(|e| e.to_string())
+}
+
+/// Subtitle styles can be picked for a video
+#[tauri::command]
+fn list_subtitle_styles() -> Vec<StylePreset> {
+    ass::presets()
+}
+
+/// Only crawled, no API key is needed
+#[tauri::command]
+async fn get_hot_news(state: State<'_, ConfigState>) -> Result<Vec<NewsTitle>, String> {
+    let config = state.get();
+
+    Ok(news_crawler(&config).get_hot_news_list().await)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn gen_video(
+    state: State<'_, ConfigState>,
+    secrets: State<'_, SecretsState>,
+    jobs: State<'_, JobLimiter>,
+    http: State<'_, HttpState>,
+    news_title: NewsTitle,
+    tts_options: Option<AliTTSOptions>,
+    voice_effects: Option<EffectChain>,
+    music: Option<String>,
+    subtitle_style: Option<String>,
+) -> Result<String, String> {
+    let mut config = state.get();
+    // the voice picked for this video, instead of the configured one
+    if let Some(tts_options) = tts_options {
+        config.tts.ali = tts_options;
+    }
+    if let Some(voice_effects) = voice_effects {
+        config.tts.effects = voice_effects;
+    }
+    // a track picked for this video is played even if music is off by default
+    if let Some(music) = music {
+        config.music.enabled = true;
+        config.music.track = music;
+    }
+    // a style picked for this video is drawn even if the plain SRT is the default
+    if let Some(subtitle_style) = subtitle_style {
+        config.subtitle.format = SubtitleFormat::Ass;
+        config.subtitle.style = subtitle_style;
+    }
+    config.validate().map_err(|e| e.to_string())?;
+
+    let _permit = jobs.0.acquire().await.map_err(|e| e.to_string())?;
+
+    // read per video, so edits of the lexicon apply without restart
+    let lexicon = if config.tts.normalize.enabled {
+        Lexicon::load(&config.tts.normalize.lexicon).map_err(|e| e.to_string())?
+    } else {
+        Lexicon::default()
+    };
+
+    let mut director =
+        build_director(&config, &secrets.0, &http.0, lexicon).map_err(|e| secrets.error(e))?;
+
+    let video = director
         .shot_single(&news_title)
         .await
-        .unwrap()
-        .path
-        .display()
-        .to_string()
+        .map_err(|e| secrets.error(e))?;
+
+    Ok(video.path.display().to_string())
+}
+
+fn news_crawler(_config: &AppConfig) -> Box<dyn NewsCrawler + Sync + Send + 'static> {
+    // `sources.news` is validated, pengpai is the only one yet
+    Box::new(PengPaiNews::new())
+}
+
+fn news_source(config: &AppConfig, secrets: &SecretStore) -> secrets::SecretsResult<NewsSource> {
+    let key = secrets.resolve(ApiKeyKind::DeepSeek)?;
+    let credentials = Credentials::new(key.expose(), config.llm.base_url.clone());
+
+    Ok(NewsSource {
+        crawler: news_crawler(config),
+        extractor: PengPaiNewsMaterialExtractor::new(credentials, config.llm.model.clone()).into(),
+    })
+}
+
+fn build_director(
+    config: &AppConfig,
+    secrets: &SecretStore,
+    http: &Client,
+    lexicon: Lexicon,
+) -> secrets::SecretsResult<Director> {
+    let director = Director::new(news_source(config, secrets)?);
+    let srt = || {
+        SrtSubtitle::new()
+            .with_max_chars(config.subtitle.max_chars)
+            .with_colors(config.subtitle.colors.clone())
+    };
+    // a soft track is validated to be SRT
+    let director = match (config.subtitle.mode, config.subtitle.format) {
+        // the SRT of the track is saved beside the video as well
+        (SubtitleMode::Soft, _) => director.with_subtitle(srt()).with_sidecar(
+            VttSubtitle::new()
+                .with_max_chars(config.subtitle.max_chars)
+                .with_colors(config.subtitle.colors.clone()),
+        ),
+        (SubtitleMode::Burn, SubtitleFormat::Srt) => director.with_subtitle(srt()),
+        // the style is validated
+        (SubtitleMode::Burn, SubtitleFormat::Ass) => director.with_subtitle(
+            AssSubtitle::new()
+                .with_max_chars(config.subtitle.max_chars)
+                .with_colors(config.subtitle.colors.clone())
+                .with_style(
+                    AssStyle::preset(&config.subtitle.style).unwrap_or_else(AssStyle::classic),
+                )
+                .with_resolution(config.video.width, config.video.height),
+        ),
+    };
+    let director = director
+        .with_subtitle_mode(config.subtitle.mode)
+        .with_video_editor(
+            JuniorEditor::new()
+                .with_format(config.video.clone())
+                .with_retry(config.retry.clone())
+                .with_client(http.clone()),
+        )
+        .with_voice_effects(config.tts.effects.clone())
+        .with_assembly(config.dubbing.clone())
+        .with_length(config.length.clone())
+        .with_loudness(config.loudness.clone())
+        .with_music(config.music.clone())
+        .with_sfx(config.sfx.clone())
+        .with_temp_dir(&config.paths.temp_dir)
+        .with_output(VideoOutput::new(
+            &config.paths.output_dir,
+            &config.paths.name_template,
+        ))
+        .with_keep_artifacts(config.paths.keep_artifacts);
+
+    let tts = match config.tts.provider {
+        TtsProvider::Ali => {
+            let key = secrets.resolve(ApiKeyKind::DashScope)?;
+            let ali = |voice: &str| {
+                let options = AliTTSOptions {
+                    voice: voice.to_owned(),
+                    ..config.tts.ali.clone()
+                };
+                AliTTS::new(config.tts.url.clone(), key.expose().to_owned())
+                    .with_options(options)
+                    .with_limits(config.concurrency.tts.clone())
+                    .with_retry(config.retry.clone())
+                    .with_client(http.clone())
+            };
+            cast(config, &lexicon, &config.tts.ali.voice, ali)
+        }
+        TtsProvider::Http => {
+            // the service has a single voice
+            let tts = HttpTTSSer::new(config.tts.url.clone())
+                .with_retry(config.retry.clone())
+                .with_client(http.clone());
+            CastTTS::new(with_stages(tts, config, &lexicon))
+        }
+        TtsProvider::Local => {
+            let local = |voice: &str| {
+                LocalTTS::new(config.tts.local.clone())
+                    .with_voice(voice)
+                    .with_limits(config.concurrency.tts.clone())
+            };
+            cast(config, &lexicon, &config.tts.local.voice, local)
+        }
+    };
+
+    Ok(director.with_tts(tts))
+}
+
+/// The anchor and every voice of `tts.roles`, built by `voice`
+fn cast<T: TTSService + Send + Sync + 'static>(
+    config: &AppConfig,
+    lexicon: &Lexicon,
+    anchor: &str,
+    voice: impl Fn(&str) -> T,
+) -> CastTTS {
+    config.tts.roles.iter().fold(
+        CastTTS::new(with_stages(voice(anchor), config, lexicon)),
+        |cast, (role, name)| cast.with_role(*role, with_stages(voice(name), config, lexicon)),
+    )
+}
+
+/// Cache and normalisation around a voice, normalised first so the spoken text is the cache key
+fn with_stages(
+    tts: impl TTSService + Send + Sync + 'static,
+    config: &AppConfig,
+    lexicon: &Lexicon,
+) -> Box<dyn TTSService + Send + Sync> {
+    let tts: Box<dyn TTSService + Send + Sync> = if config.tts.cache.enabled {
+        Box::new(CachedTTS::new(tts, &config.tts.cache))
+    } else {
+        Box::new(tts)
+    };
+
+    if config.tts.normalize.enabled {
+        Box::new(NormalizedTTS::new(tts, Normalizer::new(lexicon.clone())))
+    } else {
+        tts
+    }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet, get_hot_news, gen_video])
+        .setup(|app| {
+            let path = app.path().app_config_dir()?.join(CONFIG_FILE_NAME);
+            let config = AppConfig::load(&path)?;
+
+            // a passphrase protects the keys better, the keyfile keeps the app usable without prompt
+            let data_dir = app.path().app_data_dir()?;
+            let key_source = match dotenv::var("SECRETS_PASSPHRASE") {
+                Ok(passphrase) => KeySource::Passphrase(passphrase),
+                Err(_) => KeySource::Keyfile(data_dir.join(KEYFILE_NAME)),
+            };
+            let secrets = SecretStore::open(data_dir.join(SECRETS_FILE_NAME), key_source)?;
+
+            app.manage(SecretsState(secrets));
+            app.manage(HttpState(Client::new()));
+            app.manage(JobLimiter(Semaphore::new(
+                config.concurrency.max_jobs.max(1),
+            )));
+            app.manage(ConfigState {
+                path,
+                config: RwLock::new(config),
+            });
+
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            get_hot_news,
+            gen_video,
+            get_config,
+            update_config,
+            get_api_keys,
+            set_api_key,
+            clear_api_key,
+            test_api_key,
+            list_voices,
+            list_voice_effects,
+            list_music,
+            list_subtitle_styles
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -5,10 +5,11 @@ use dotenv::dotenv;
 
 #[tokio::main]
 async fn main() {
+    // env file is optional, it only overrides the config file
     if cfg!(debug_assertions) {
-        dotenv::from_filename(".env.dev").unwrap();
+        _ = dotenv::from_filename(".env.dev");
     } else {
-        dotenv().unwrap();
+        _ = dotenv();
     }
 
     ndclient_lib::run()
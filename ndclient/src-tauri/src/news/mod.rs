@@ -23,19 +23,147 @@ impl NewsTitle {
 pub type NewsMaterialResult = Result<NewsMaterial, NewsMaterialError>;
 
 #[derive(Error, Debug)]
-pub enum NewsMaterialError {}
+pub enum NewsMaterialError {
+    #[error("bad summary: {0}")]
+    Summary(String),
+}
 
 pub struct NewsMaterial {
     pub title: String,
-    pub summary: Vec<String>,
+    pub summary: Vec<Sentence>,
     pub pics: Vec<String>,
     pub videos: Vec<String>,
 }
 
+/// Who speaks a sentence of the summary, every role can have its own voice
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeakerRole {
+    /// the narrator
+    #[default]
+    Anchor,
+    /// a quoted man
+    Male,
+    /// a quoted woman
+    Female,
+    /// a quoted child
+    Child,
+}
+
+/// A sentence of the summary and who speaks it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawSentence")]
+pub struct Sentence {
+    pub text: String,
+    pub role: SpeakerRole,
+    /// a climax of the news, can be stressed by a sound effect
+    pub emphasis: bool,
+    /// how much the story needs it, 1 ~ 3, the lowest are dropped first to fit a length
+    pub priority: u8,
+}
+
+pub const DEFAULT_PRIORITY: u8 = 2;
+
+impl Sentence {
+    /// A sentence of the anchor
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            role: SpeakerRole::Anchor,
+            emphasis: false,
+            priority: DEFAULT_PRIORITY,
+        }
+    }
+
+    pub fn with_role(mut self, role: SpeakerRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    pub fn with_emphasis(mut self, emphasis: bool) -> Self {
+        self.emphasis = emphasis;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// A plain string is accepted too, spoken by the anchor
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSentence {
+    Plain(String),
+    Tagged {
+        text: String,
+        #[serde(default)]
+        role: RawRole,
+        #[serde(default)]
+        emphasis: bool,
+        /// any number, rounded into 1 ~ 3
+        #[serde(default)]
+        priority: Option<f64>,
+    },
+}
+
+/// The role as the summary tags it, the unknown ones are read by the anchor
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawRole {
+    #[default]
+    Anchor,
+    Male,
+    Female,
+    Child,
+    #[serde(other)]
+    Other,
+}
+
+impl From<RawRole> for SpeakerRole {
+    fn from(raw: RawRole) -> Self {
+        match raw {
+            RawRole::Anchor | RawRole::Other => Self::Anchor,
+            RawRole::Male => Self::Male,
+            RawRole::Female => Self::Female,
+            RawRole::Child => Self::Child,
+        }
+    }
+}
+
+impl From<RawSentence> for Sentence {
+    fn from(raw: RawSentence) -> Self {
+        match raw {
+            RawSentence::Plain(text) => Self::new(text),
+            RawSentence::Tagged {
+                text,
+                role,
+                emphasis,
+                priority,
+            } => Self {
+                text,
+                role: role.into(),
+                emphasis,
+                priority: priority.map_or(DEFAULT_PRIORITY, |priority| {
+                    priority.round().clamp(1.0, 3.0) as u8
+                }),
+            },
+        }
+    }
+}
+
 /// crawler, indicats how to get the news title list
 #[async_trait::async_trait]
 pub trait NewsCrawler {
     async fn get_hot_news_list(&self) -> Vec<NewsTitle>;
+
+    /// short name of the source, used to name the output videos
+    fn source_name(&self) -> &str {
+        "news"
+    }
 }
 
 /// Extractor, responsible for extractor news material: news summary, pictures, videos.
@@ -43,3 +171,44 @@ pub trait NewsCrawler {
 pub trait MaterialExtractor {
     async fn get_material(&self, hot_news: &NewsTitle) -> NewsMaterialResult;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sentences() {
+        let sentences: Vec<Sentence> = serde_json::from_str(
+            r#"["旁白", {"text": "他说", "role": "male", "priority": 1}, {"text": "没有角色", "emphasis": true}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sentences,
+            [
+                Sentence::new("旁白"),
+                Sentence::new("他说")
+                    .with_role(SpeakerRole::Male)
+                    .with_priority(1),
+                Sentence::new("没有角色").with_emphasis(true),
+            ]
+        );
+    }
+
+    #[test]
+    fn lenient_sentences() {
+        let sentences: Vec<Sentence> = serde_json::from_str(
+            r#"[{"text": "老人说", "role": "elder", "priority": 2.5}, {"text": "重要", "priority": 9}, {"text": "铺垫", "priority": -1}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sentences,
+            [
+                Sentence::new("老人说").with_priority(3),
+                Sentence::new("重要").with_priority(3),
+                Sentence::new("铺垫").with_priority(1),
+            ]
+        );
+    }
+}
@@ -6,7 +6,10 @@ use reqwest::Client;
 use scraper::Selector;
 use std::{cell::RefCell, sync::Mutex};
 
-use crate::news::{MaterialExtractor, NewsCrawler, NewsMaterial, NewsMaterialResult, NewsTitle};
+use crate::news::{
+    MaterialExtractor, NewsCrawler, NewsMaterial, NewsMaterialError, NewsMaterialResult, NewsTitle,
+    Sentence,
+};
 
 pub struct PengPaiNews {
     client: Client,
@@ -45,6 +48,10 @@ impl NewsCrawler for PengPaiNews {
             .map(PengPaiHotNews::to_hot_news)
             .collect()
     }
+
+    fn source_name(&self) -> &str {
+        "pengpai"
+    }
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -100,14 +107,22 @@ struct Video {
 
 pub struct PengPaiNewsMaterialExtractor {
     credentials: Credentials,
+    model: String,
 }
 
 impl PengPaiNewsMaterialExtractor {
-    pub fn from_deepseek() -> Self {
+    /// `credentials`: any OpenAI compatible API
+    /// `model`: the chat model name, e.g. `deepseek-chat`
+    pub fn new(credentials: Credentials, model: impl Into<String>) -> Self {
         Self {
-            credentials: Credentials::from_env(),
+            credentials,
+            model: model.into(),
         }
     }
+
+    pub fn from_deepseek() -> Self {
+        Self::new(Credentials::from_env(), "deepseek-chat")
+    }
 }
 
 #[async_trait::async_trait]
@@ -119,7 +134,7 @@ impl MaterialExtractor for PengPaiNewsMaterialExtractor {
 
         let body = Self::get_body_inner_text(raw_content);
 
-        let deepseek_result = self.ask_deepseek(&body).await;
+        let deepseek_result = self.ask_deepseek(&body).await?;
 
         let mut pics = hot_news.pics.clone();
         pics.extend(deepseek_result.images);
@@ -135,7 +150,7 @@ impl MaterialExtractor for PengPaiNewsMaterialExtractor {
 
 #[derive(Debug, serde::Deserialize, Clone)]
 struct DeepSeekResult {
-    pub summary: Vec<String>,
+    pub summary: Vec<Sentence>,
     pub images: Vec<String>,
 }
 
@@ -150,7 +165,7 @@ impl PengPaiNewsMaterialExtractor {
         body.inner_html()
     }
 
-    async fn ask_deepseek(&self, news_content: &str) -> DeepSeekResult {
+    async fn ask_deepseek(&self, news_content: &str) -> Result<DeepSeekResult, NewsMaterialError> {
         let messages = vec![
             ChatCompletionMessage {
                 role: ChatCompletionMessageRole::System,
@@ -170,7 +185,7 @@ impl PengPaiNewsMaterialExtractor {
             },
         ];
 
-        let chat_completion = ChatCompletion::builder("deepseek-chat", messages.clone())
+        let chat_completion = ChatCompletion::builder(&self.model, messages.clone())
             .credentials(self.credentials.clone())
             .create()
             .await
@@ -180,7 +195,7 @@ impl PengPaiNewsMaterialExtractor {
 
         let raw_json = returned_message
             .content
-            .unwrap()
+            .ok_or_else(|| NewsMaterialError::Summary("empty answer".to_owned()))?
             .trim_start_matches("```json")
             .trim_start_matches("```JSON")
             .trim_end_matches("```")
@@ -188,17 +203,17 @@ impl PengPaiNewsMaterialExtractor {
             .to_string();
 
         println!("raw_json: {}", raw_json);
-        serde_json::from_str(&raw_json).unwrap()
+        serde_json::from_str(&raw_json).map_err(|e| NewsMaterialError::Summary(e.to_string()))
     }
 
     fn get_prompt() -> &'static str {
         r#"
 你是一个爆款短视频的作者，我会给你一个 HTML 格式的新闻稿，你要根据要求总结里面的新闻，并提取正文的图片，具体要求为：
 1. 将新闻内容浓缩为200字内的短视频风格摘要，严格控制在200字以内，使用吸引眼球的短视频的风格夸张语气和俏皮。
-风格夸张俏皮，喜欢使用网络热词和热梗，保持事实准确，突出核心事件、关键人物和戏剧性细节，纯文字输出，禁止使用表情符号，时间地点人物等关键信息必须准确，注意中文标点符号使用规范。正文要根据逗号、句号分割，放在数组内，如：
+风格夸张俏皮，喜欢使用网络热词和热梗，保持事实准确，突出核心事件、关键人物和戏剧性细节，纯文字输出，禁止使用表情符号，时间地点人物等关键信息必须准确，注意中文标点符号使用规范。正文要根据逗号、句号分割，放在数组内，每个句子标注说话的角色 role：主播旁白为 "anchor"，直接引用的男性说的话为 "male"，女性为 "female"，儿童为 "child"，拿不准时用 "anchor"。全文最具冲击力的一两个句子（反转、高潮、关键数字）标注 "emphasis": true，其余不标注。每个句子标注重要程度 priority：核心事实为 3，一般叙述为 2，可删去的铺垫、评论为 1，如：
 
 ```json
-["句子1", "句子2"]
+[{"text": "句子1", "role": "anchor", "priority": 3}, {"text": "句子2", "role": "male", "emphasis": true, "priority": 2}]
 ```
 
 2. 从新闻稿HTML中提取仅正文部分的图片链接（排除封面、视频缩略图、图标等非正文内容），并去除URL中的querystring参数。严格限定在正文内容区域，排除所有非正文图片（封面/视频缩略图/广告等），清除URL中?及后面的参数，结果以JSON数组格式返回，若无符合条件图片则返回空数组[]。如：
@@ -213,7 +228,7 @@ impl PengPaiNewsMaterialExtractor {
 上面两点要求按照 JSON 格式输出，如：
 
 {
-  "summary": ["句子1", "句子2"],
+  "summary": [{"text": "句子1", "role": "anchor"}, {"text": "句子2", "role": "female"}],
   "images": ["图片1", "图片2"]
 }
         "#
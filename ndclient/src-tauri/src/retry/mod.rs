@@ -0,0 +1,282 @@
+use std::{fmt, future::Future, time::Duration};
+
+use rand::Rng;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// How a failed network request is retried, shared by the TTS services and picture downloads.
+///
+/// Every attempt is limited by `timeout_secs`, and waits an exponential backoff
+/// with jitter before the next one. Only transient failures are retried, see [`Failure`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// attempts in total, `1` means no retry
+    pub max_attempts: u32,
+    /// backoff before the second attempt, doubled after every attempt
+    pub base_delay_ms: u64,
+    /// upper bound of the backoff
+    pub max_delay_ms: u64,
+    /// timeout of a single attempt
+    pub timeout_secs: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 8_000,
+            timeout_secs: 60,
+        }
+    }
+}
+
+/// Why an attempt failed, decides whether it is worth another try
+#[derive(Debug)]
+pub enum Failure {
+    /// timeouts, connection errors, `5xx`, `408` and `429`
+    Transient(String),
+    /// anything a retry cannot fix, e.g. `4xx` or a malformed response
+    Permanent(String),
+}
+
+impl Failure {
+    /// Classify a non-success HTTP status, `body` is kept for the error message
+    pub fn from_status(status: StatusCode, body: &str) -> Self {
+        let reason = format!("server returned {status}: {body}");
+
+        if status.is_server_error()
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::TOO_MANY_REQUESTS
+        {
+            Failure::Transient(reason)
+        } else {
+            Failure::Permanent(reason)
+        }
+    }
+}
+
+impl From<reqwest::Error> for Failure {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() {
+            Failure::Transient(e.to_string())
+        } else if let Some(status) = e.status() {
+            Failure::from_status(status, "")
+        } else {
+            Failure::Permanent(e.to_string())
+        }
+    }
+}
+
+/// The last failure, and how many attempts were made
+#[derive(Debug)]
+pub struct RetryError {
+    pub attempts: u32,
+    pub reason: String,
+}
+
+impl fmt::Display for RetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.attempts == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} (after {} attempt{plural})",
+            self.reason, self.attempts
+        )
+    }
+}
+
+impl std::error::Error for RetryError {}
+
+impl RetryPolicy {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_attempts == 0 {
+            return Err("max_attempts must be greater than 0".to_owned());
+        }
+        if self.timeout_secs == 0 {
+            return Err("timeout_secs must be greater than 0".to_owned());
+        }
+        if self.base_delay_ms > self.max_delay_ms {
+            return Err("base_delay_ms cannot be greater than max_delay_ms".to_owned());
+        }
+
+        Ok(())
+    }
+
+    /// Run `attempt` until it succeeds, fails permanently or runs out of attempts.
+    ///
+    /// Only idempotent operations should be passed in, every attempt starts the request from scratch.
+    pub async fn run<T, F, Fut>(&self, mut attempt: F) -> Result<T, RetryError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Failure>>,
+    {
+        let max_attempts = self.max_attempts.max(1);
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let failure = match tokio::time::timeout(self.timeout(), attempt()).await {
+                Ok(Ok(value)) => return Ok(value),
+                Ok(Err(failure)) => failure,
+                Err(_) => Failure::Transient(format!("timed out after {}s", self.timeout_secs)),
+            };
+
+            match failure {
+                Failure::Transient(_) if attempts < max_attempts => {
+                    tokio::time::sleep(self.backoff(attempts)).await;
+                }
+                Failure::Transient(reason) | Failure::Permanent(reason) => {
+                    return Err(RetryError { attempts, reason });
+                }
+            }
+        }
+    }
+
+    /// Wait after the `attempts`th failure, the "equal jitter" backoff:
+    /// half of the exponential delay is fixed, the other half is random
+    fn backoff(&self, attempts: u32) -> Duration {
+        let exp = self
+            .base_delay_ms
+            .saturating_mul(1 << (attempts - 1).min(16))
+            .min(self.max_delay_ms);
+
+        let half = exp / 2;
+        let jitter = rand::thread_rng().gen_range(0..=exp - half);
+
+        Duration::from_millis(half + jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay_ms: 1,
+            max_delay_ms: 5,
+            timeout_secs: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_transient_until_success() {
+        let calls = AtomicU32::new(0);
+
+        let result = fast_policy()
+            .run(|| async {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(Failure::Transient("503".to_owned()))
+                } else {
+                    Ok("done")
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), "done");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn give_up_after_max_attempts() {
+        let calls = AtomicU32::new(0);
+
+        let result: Result<(), _> = fast_policy()
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(Failure::Transient("503".to_owned()))
+            })
+            .await;
+
+        let e = result.unwrap_err();
+        assert_eq!(e.attempts, 3);
+        assert_eq!(e.to_string(), "503 (after 3 attempts)");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn permanent_is_not_retried() {
+        let calls = AtomicU32::new(0);
+
+        let result: Result<(), _> = fast_policy()
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(Failure::Permanent("401".to_owned()))
+            })
+            .await;
+
+        assert_eq!(result.unwrap_err().to_string(), "401 (after 1 attempt)");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_server_error_status() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let mock = server
+            .mock("GET", "/image")
+            .with_status(503)
+            .expect(3)
+            .create();
+
+        let http = reqwest::Client::new();
+        let result = fast_policy()
+            .run(|| async {
+                let response = http.get(format!("{url}/image")).send().await?;
+                if !response.status().is_success() {
+                    return Err(Failure::from_status(response.status(), ""));
+                }
+                Ok(())
+            })
+            .await;
+
+        mock.assert();
+        assert_eq!(result.unwrap_err().attempts, 3);
+    }
+
+    #[test]
+    fn classify_status() {
+        assert!(matches!(
+            Failure::from_status(StatusCode::BAD_GATEWAY, ""),
+            Failure::Transient(_)
+        ));
+        assert!(matches!(
+            Failure::from_status(StatusCode::TOO_MANY_REQUESTS, ""),
+            Failure::Transient(_)
+        ));
+        assert!(matches!(
+            Failure::from_status(StatusCode::NOT_FOUND, ""),
+            Failure::Permanent(_)
+        ));
+    }
+
+    #[test]
+    fn backoff_grows_within_bounds() {
+        let policy = RetryPolicy {
+            base_delay_ms: 100,
+            max_delay_ms: 1_000,
+            ..Default::default()
+        };
+
+        for _ in 0..20 {
+            let first = policy.backoff(1).as_millis();
+            assert!((50..=100).contains(&first));
+
+            let third = policy.backoff(3).as_millis();
+            assert!((200..=400).contains(&third));
+
+            let capped = policy.backoff(10).as_millis();
+            assert!((500..=1_000).contains(&capped));
+        }
+    }
+}
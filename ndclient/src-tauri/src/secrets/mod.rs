@@ -0,0 +1,495 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use argon2::Argon2;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::{
+    Key, XChaCha20Poly1305, XNonce,
+    aead::{Aead, KeyInit},
+};
+use rand::{RngCore, rngs::OsRng};
+use reqwest::{Client, StatusCode, header};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const SECRETS_FILE_NAME: &str = "secrets.enc";
+pub const KEYFILE_NAME: &str = "secrets.key";
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const FILE_VERSION: u32 = 1;
+
+pub type SecretsResult<T> = Result<T, SecretsError>;
+
+#[derive(Error, Debug)]
+pub enum SecretsError {
+    #[error("secrets file error: {0}")]
+    File(#[from] io::Error),
+    #[error("malformed secrets file: {0}")]
+    Format(String),
+    #[error("cannot decrypt secrets, wrong passphrase or keyfile")]
+    Decrypt,
+    #[error("{0} API key is not set")]
+    Missing(ApiKeyKind),
+    #[error("{0} API key is rejected: {1}")]
+    Rejected(ApiKeyKind, String),
+    #[error("network error: {0}")]
+    Network(String),
+}
+
+/// API keys the app needs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyKind {
+    /// the LLM extracting news material
+    DeepSeek,
+    /// Ali TTS
+    DashScope,
+}
+
+impl ApiKeyKind {
+    pub const ALL: [ApiKeyKind; 2] = [ApiKeyKind::DeepSeek, ApiKeyKind::DashScope];
+
+    /// the environment variable used before the secret store existed, still used as fallback
+    pub fn env_var(&self) -> &'static str {
+        match self {
+            ApiKeyKind::DeepSeek => "OPENAI_KEY",
+            ApiKeyKind::DashScope => "ALI_DASHSCOPE_API_KEY",
+        }
+    }
+}
+
+impl fmt::Display for ApiKeyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiKeyKind::DeepSeek => write!(f, "DeepSeek"),
+            ApiKeyKind::DashScope => write!(f, "DashScope"),
+        }
+    }
+}
+
+/// A secret value, it is masked when printed by `Debug` or `Display`
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// the plain value, only pass it to where it is really used
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn masked(&self) -> String {
+        mask(&self.0)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", self.masked())
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.masked())
+    }
+}
+
+/// How the encryption key is derived
+pub enum KeySource {
+    /// derived from a user passphrase by Argon2id
+    Passphrase(String),
+    /// 32 random bytes stored in a file, created when missing
+    Keyfile(PathBuf),
+}
+
+/// API keys encrypted at rest by XChaCha20-Poly1305
+pub struct SecretStore {
+    path: PathBuf,
+    salt: [u8; SALT_LEN],
+    key: [u8; KEY_LEN],
+}
+
+#[derive(Serialize, Deserialize)]
+struct SecretsFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+impl SecretStore {
+    /// Open the store at `path`, the file is created on the first `set`.
+    /// Fails with [`SecretsError::Decrypt`] if the existing file is encrypted by another key.
+    pub fn open(path: impl Into<PathBuf>, key_source: KeySource) -> SecretsResult<Self> {
+        let path = path.into();
+
+        let salt = match Self::read_file(&path)? {
+            Some(file) => decode_array(&file.salt, "salt")?,
+            None => {
+                let mut salt = [0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                salt
+            }
+        };
+
+        let key = match key_source {
+            KeySource::Passphrase(passphrase) => {
+                let mut key = [0u8; KEY_LEN];
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+                    .map_err(|e| SecretsError::Format(e.to_string()))?;
+                key
+            }
+            KeySource::Keyfile(keyfile) => Self::load_keyfile(&keyfile)?,
+        };
+
+        let store = Self { path, salt, key };
+        // fail early on a wrong key
+        store.load()?;
+
+        Ok(store)
+    }
+
+    pub fn get(&self, kind: ApiKeyKind) -> SecretsResult<Option<Secret>> {
+        Ok(self.load()?.remove(&kind).map(Secret))
+    }
+
+    pub fn set(&self, kind: ApiKeyKind, value: &str) -> SecretsResult<()> {
+        let mut secrets = self.load()?;
+        secrets.insert(kind, value.trim().to_owned());
+        self.save(&secrets)
+    }
+
+    pub fn clear(&self, kind: ApiKeyKind) -> SecretsResult<()> {
+        let mut secrets = self.load()?;
+        secrets.remove(&kind);
+        self.save(&secrets)
+    }
+
+    /// The stored key, or the environment variable as fallback
+    pub fn resolve(&self, kind: ApiKeyKind) -> SecretsResult<Secret> {
+        if let Some(secret) = self.get(kind)? {
+            return Ok(secret);
+        }
+
+        dotenv::var(kind.env_var())
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(Secret)
+            .ok_or(SecretsError::Missing(kind))
+    }
+
+    /// Replace every stored (or env) secret inside `text` by its masked form,
+    /// use it on any message which may be logged or shown
+    pub fn redact(&self, text: &str) -> String {
+        let secrets: Vec<Secret> = ApiKeyKind::ALL
+            .iter()
+            .filter_map(|kind| self.resolve(*kind).ok())
+            .collect();
+
+        redact(text, &secrets)
+    }
+
+    fn load(&self) -> SecretsResult<BTreeMap<ApiKeyKind, String>> {
+        let Some(file) = Self::read_file(&self.path)? else {
+            return Ok(BTreeMap::new());
+        };
+
+        let nonce: [u8; NONCE_LEN] = decode_array(&file.nonce, "nonce")?;
+        let data = STANDARD
+            .decode(&file.data)
+            .map_err(|e| SecretsError::Format(e.to_string()))?;
+
+        let plain = self
+            .cipher()
+            .decrypt(XNonce::from_slice(&nonce), data.as_slice())
+            .map_err(|_| SecretsError::Decrypt)?;
+
+        serde_json::from_slice(&plain).map_err(|e| SecretsError::Format(e.to_string()))
+    }
+
+    fn save(&self, secrets: &BTreeMap<ApiKeyKind, String>) -> SecretsResult<()> {
+        let plain = serde_json::to_vec(secrets).map_err(|e| SecretsError::Format(e.to_string()))?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+
+        let data = self
+            .cipher()
+            .encrypt(XNonce::from_slice(&nonce), plain.as_slice())
+            .map_err(|e| SecretsError::Format(e.to_string()))?;
+
+        let file = SecretsFile {
+            version: FILE_VERSION,
+            salt: STANDARD.encode(self.salt),
+            nonce: STANDARD.encode(nonce),
+            data: STANDARD.encode(data),
+        };
+        let content =
+            serde_json::to_vec_pretty(&file).map_err(|e| SecretsError::Format(e.to_string()))?;
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // never leave a half written file
+        let tmp = self.path.with_extension("tmp");
+        _ = fs::remove_file(&tmp);
+        write_private(&tmp, &content)?;
+        fs::rename(tmp, &self.path)?;
+
+        Ok(())
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(Key::from_slice(&self.key))
+    }
+
+    fn read_file(path: &Path) -> SecretsResult<Option<SecretsFile>> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let file: SecretsFile =
+            serde_json::from_slice(&content).map_err(|e| SecretsError::Format(e.to_string()))?;
+
+        if file.version != FILE_VERSION {
+            return Err(SecretsError::Format(format!(
+                "unsupported version {}",
+                file.version
+            )));
+        }
+
+        Ok(Some(file))
+    }
+
+    fn load_keyfile(path: &Path) -> SecretsResult<[u8; KEY_LEN]> {
+        match fs::read(path) {
+            Ok(bytes) => bytes
+                .try_into()
+                .map_err(|_| SecretsError::Format(format!("keyfile must be {KEY_LEN} bytes"))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut key = [0u8; KEY_LEN];
+                OsRng.fill_bytes(&mut key);
+
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_private(path, &key)?;
+
+                Ok(key)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Create a new file only the user can read, the keyfile decrypts every secret
+fn write_private(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(content)
+}
+
+fn decode_array<const N: usize>(value: &str, name: &str) -> SecretsResult<[u8; N]> {
+    STANDARD
+        .decode(value)
+        .map_err(|e| SecretsError::Format(e.to_string()))?
+        .try_into()
+        .map_err(|_| SecretsError::Format(format!("{name} must be {N} bytes")))
+}
+
+/// Mask a secret, only the first 3 and last 4 characters of a long one are kept
+pub fn mask(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() < 12 {
+        return "****".to_owned();
+    }
+
+    let head: String = chars[..3].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{head}****{tail}")
+}
+
+/// Replace every secret inside `text` by its masked form
+pub fn redact(text: &str, secrets: &[Secret]) -> String {
+    secrets
+        .iter()
+        .filter(|s| !s.expose().is_empty())
+        .fold(text.to_owned(), |text, secret| {
+            text.replace(secret.expose(), &secret.masked())
+        })
+}
+
+/// OpenAI compatible base URL of DashScope, for checking its key
+pub const DASHSCOPE_BASE_URL: &str = "https://dashscope.aliyuncs.com/compatible-mode/v1";
+
+/// List the models of the provider to check the key is accepted, nothing is billed.
+/// `url`: the OpenAI compatible base URL of the provider
+pub async fn check_api_key(kind: ApiKeyKind, key: &Secret, url: &str) -> SecretsResult<()> {
+    let response = Client::new()
+        .get(format!("{}/models", url.trim_end_matches('/')))
+        .header(header::AUTHORIZATION, format!("Bearer {}", key.expose()))
+        .send()
+        .await
+        .map_err(|e| SecretsError::Network(redact(&e.to_string(), &[key.clone()])))?;
+
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            let body = response.text().await.unwrap_or_default();
+            Err(SecretsError::Rejected(kind, redact(&body, &[key.clone()])))
+        }
+        status => Err(SecretsError::Network(format!("{kind} returned {status}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header;
+
+    use super::*;
+    use crate::workspace::{DEFAULT_TEMP_DIR, Workspace};
+
+    const KEY: &str = "sk-1234567890abcdef";
+
+    #[test]
+    fn keyfile_roundtrip() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = workspace.path(SECRETS_FILE_NAME);
+        let keyfile = workspace.path(KEYFILE_NAME);
+
+        let store = SecretStore::open(&path, KeySource::Keyfile(keyfile.clone())).unwrap();
+        assert_eq!(store.get(ApiKeyKind::DeepSeek).unwrap(), None);
+
+        store.set(ApiKeyKind::DeepSeek, KEY).unwrap();
+        store
+            .set(ApiKeyKind::DashScope, "another-secret-key")
+            .unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains(KEY));
+
+        let store = SecretStore::open(&path, KeySource::Keyfile(keyfile)).unwrap();
+        assert_eq!(
+            store.get(ApiKeyKind::DeepSeek).unwrap(),
+            Some(Secret::new(KEY))
+        );
+
+        store.clear(ApiKeyKind::DeepSeek).unwrap();
+        assert_eq!(store.get(ApiKeyKind::DeepSeek).unwrap(), None);
+        assert_eq!(
+            store.get(ApiKeyKind::DashScope).unwrap(),
+            Some(Secret::new("another-secret-key"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_only_for_the_user() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = workspace.path(SECRETS_FILE_NAME);
+        let keyfile = workspace.path(KEYFILE_NAME);
+
+        let store = SecretStore::open(&path, KeySource::Keyfile(keyfile.clone())).unwrap();
+        store.set(ApiKeyKind::DeepSeek, KEY).unwrap();
+        // saved again over the first file
+        store.set(ApiKeyKind::DashScope, KEY).unwrap();
+
+        for file in [path, keyfile] {
+            let mode = fs::metadata(&file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", file.display());
+        }
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = workspace.path(SECRETS_FILE_NAME);
+
+        let store = SecretStore::open(&path, KeySource::Passphrase("right".to_owned())).unwrap();
+        store.set(ApiKeyKind::DashScope, KEY).unwrap();
+
+        let store = SecretStore::open(&path, KeySource::Passphrase("right".to_owned())).unwrap();
+        assert_eq!(
+            store.get(ApiKeyKind::DashScope).unwrap(),
+            Some(Secret::new(KEY))
+        );
+
+        let wrong = SecretStore::open(&path, KeySource::Passphrase("wrong".to_owned()));
+        assert!(matches!(wrong, Err(SecretsError::Decrypt)));
+    }
+
+    #[test]
+    fn mask_and_redact() {
+        let secret = Secret::new(KEY);
+
+        assert_eq!(mask(KEY), "sk-****cdef");
+        assert_eq!(mask("short"), "****");
+        assert_eq!(format!("{secret:?}"), "Secret(sk-****cdef)");
+        assert_eq!(secret.to_string(), "sk-****cdef");
+
+        assert_eq!(
+            redact(&format!("invalid key {KEY}, check it"), &[secret]),
+            "invalid key sk-****cdef, check it"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_key() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let _mock = server
+            .mock("GET", "/models")
+            .with_status(401)
+            .with_header(header::CONTENT_TYPE, "application/json")
+            .with_body(format!(r#"{{"error":"invalid key {KEY}"}}"#))
+            .create();
+
+        let err = check_api_key(ApiKeyKind::DeepSeek, &Secret::new(KEY), &url)
+            .await
+            .unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.starts_with("DeepSeek API key is rejected"));
+        assert!(!message.contains(KEY));
+    }
+
+    #[tokio::test]
+    async fn accepted_key_lists_models() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("GET", "/models")
+            .match_header(
+                header::AUTHORIZATION.as_str(),
+                format!("Bearer {KEY}").as_str(),
+            )
+            .with_status(200)
+            .with_body(r#"{"data":[]}"#)
+            .create();
+
+        check_api_key(ApiKeyKind::DashScope, &Secret::new(KEY), &server.url())
+            .await
+            .unwrap();
+        mock.assert();
+    }
+}
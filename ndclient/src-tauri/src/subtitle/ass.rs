@@ -0,0 +1,366 @@
+use std::{collections::BTreeMap, fmt::Write, fs, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    news::SpeakerRole,
+    subtitle::{Subtitle, SubtitleResult, chunks},
+    timeline::Timeline,
+    workspace::Workspace,
+};
+
+pub const DEFAULT_STYLE: &str = "classic";
+
+/// Where the subtitles are placed, centred horizontally
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitlePosition {
+    Bottom,
+    Middle,
+    Top,
+}
+
+impl SubtitlePosition {
+    /// numpad alignment of ASS
+    fn alignment(self) -> u8 {
+        match self {
+            Self::Bottom => 2,
+            Self::Middle => 5,
+            Self::Top => 8,
+        }
+    }
+}
+
+/// Look of the subtitles, sizes in pixels of the video
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AssStyle {
+    pub font: String,
+    pub size: u32,
+    pub bold: bool,
+    /// `#RRGGBB` of the text
+    pub color: String,
+    /// `#RRGGBB` of the outline
+    pub outline_color: String,
+    /// `#RRGGBB` of the shadow, or of the box
+    pub back_color: String,
+    pub outline: f32,
+    pub shadow: f32,
+    /// a translucent box behind the text instead of the outline
+    pub boxed: bool,
+    pub position: SubtitlePosition,
+    /// from the top or bottom edge, unused in the middle
+    pub margin_v: u32,
+    /// from the left and right edges, long lines wrap inside
+    pub margin_h: u32,
+}
+
+/// A named style, for picking in the app
+#[derive(Clone, Debug, Serialize)]
+pub struct StylePreset {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub style: AssStyle,
+}
+
+impl AssStyle {
+    /// The style of a preset by its id
+    pub fn preset(id: &str) -> Option<Self> {
+        presets()
+            .into_iter()
+            .find(|preset| preset.id == id)
+            .map(|preset| preset.style)
+    }
+
+    /// White bold text with a black outline above the bottom,
+    /// clear of the buttons the platforms put there
+    pub fn classic() -> Self {
+        Self {
+            font: "Noto Sans CJK SC".to_owned(),
+            size: 52,
+            bold: true,
+            color: "#FFFFFF".to_owned(),
+            outline_color: "#000000".to_owned(),
+            back_color: "#000000".to_owned(),
+            outline: 3.0,
+            shadow: 1.0,
+            boxed: false,
+            position: SubtitlePosition::Bottom,
+            margin_v: 220,
+            margin_h: 60,
+        }
+    }
+}
+
+/// The styles offered in the app, tuned for 720x1280
+pub fn presets() -> Vec<StylePreset> {
+    vec![
+        StylePreset {
+            id: DEFAULT_STYLE,
+            name: "经典",
+            style: AssStyle::classic(),
+        },
+        StylePreset {
+            id: "bold",
+            name: "醒目",
+            style: AssStyle {
+                size: 64,
+                color: "#FFE14D".to_owned(),
+                outline: 5.0,
+                shadow: 2.0,
+                margin_v: 300,
+                ..AssStyle::classic()
+            },
+        },
+        StylePreset {
+            id: "boxed",
+            name: "字幕条",
+            style: AssStyle {
+                size: 46,
+                bold: false,
+                outline: 10.0,
+                shadow: 0.0,
+                boxed: true,
+                margin_v: 200,
+                ..AssStyle::classic()
+            },
+        },
+        StylePreset {
+            id: "top",
+            name: "顶部",
+            style: AssStyle {
+                position: SubtitlePosition::Top,
+                margin_v: 180,
+                ..AssStyle::classic()
+            },
+        },
+        StylePreset {
+            id: "center",
+            name: "居中",
+            style: AssStyle {
+                size: 60,
+                outline: 4.0,
+                position: SubtitlePosition::Middle,
+                margin_v: 0,
+                ..AssStyle::classic()
+            },
+        },
+    ]
+}
+
+/// Transparency of the shadow and the box, 0 is opaque and 255 invisible
+const BACK_ALPHA: u8 = 0x60;
+
+/// Styled subtitles, burned in by libass like the SRT ones
+pub struct AssSubtitle {
+    max_chars: usize,
+    /// `#RRGGBB` of the roles, the others keep the colour of the style
+    colors: BTreeMap<SpeakerRole, String>,
+    style: AssStyle,
+    width: u32,
+    height: u32,
+}
+
+#[async_trait::async_trait]
+impl Subtitle for AssSubtitle {
+    async fn write_subtitle(&self, timeline: &Timeline, workspace: &Workspace) -> SubtitleResult {
+        let mut content = self.header();
+
+        for segment in &timeline.segments {
+            let color = self
+                .colors
+                .get(&segment.role)
+                .map(|color| format!(r"{{\c{}}}", ass_color(color, 0)));
+
+            for chunk in chunks(segment, self.max_chars) {
+                let _ = writeln!(
+                    content,
+                    "Dialogue: 0,{},{},Default,,0,0,0,,{}{}",
+                    to_ass_time(segment.start + chunk.start),
+                    to_ass_time(segment.start + chunk.end),
+                    color.as_deref().unwrap_or_default(),
+                    escape(&chunk.text),
+                );
+            }
+        }
+
+        let path = workspace.unique_path("subtitle.ass");
+
+        fs::write(&path, content)?;
+
+        Ok(path)
+    }
+}
+
+impl Default for AssSubtitle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AssSubtitle {
+    pub fn new() -> Self {
+        Self {
+            max_chars: 0,
+            colors: BTreeMap::new(),
+            style: AssStyle::classic(),
+            width: 720,
+            height: 1280,
+        }
+    }
+
+    /// Split long sentences into cues of at most `max_chars` characters, `0` keeps them whole
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    /// Colour the subtitles of every speaker role, as `#RRGGBB`
+    pub fn with_colors(mut self, colors: BTreeMap<SpeakerRole, String>) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub fn with_style(mut self, style: AssStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Size of the video, the sizes of the style are in its pixels
+    pub fn with_resolution(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    fn header(&self) -> String {
+        let style = &self.style;
+        // the box of border style 3 takes the outline colour
+        let (border_style, outline_color) = if style.boxed {
+            (3, ass_color(&style.back_color, BACK_ALPHA))
+        } else {
+            (1, ass_color(&style.outline_color, 0))
+        };
+
+        format!(
+            "[Script Info]
+ScriptType: v4.00+
+PlayResX: {width}
+PlayResY: {height}
+WrapStyle: 0
+ScaledBorderAndShadow: yes
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,{font},{size},{color},{color},{outline_color},{back_color},{bold},0,0,0,100,100,0,0,{border_style},{outline},{shadow},{alignment},{margin_h},{margin_h},{margin_v},1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+",
+            width = self.width,
+            height = self.height,
+            font = style.font,
+            size = style.size,
+            color = ass_color(&style.color, 0),
+            back_color = ass_color(&style.back_color, BACK_ALPHA),
+            bold = if style.bold { -1 } else { 0 },
+            outline = style.outline,
+            shadow = style.shadow,
+            alignment = style.position.alignment(),
+            margin_h = style.margin_h,
+            margin_v = style.margin_v,
+        )
+    }
+}
+
+/// `#RRGGBB` as `&HAABBGGRR`, white if malformed
+fn ass_color(color: &str, alpha: u8) -> String {
+    let hex = |i: usize| color.get(i..i + 2).unwrap_or("FF").to_ascii_uppercase();
+
+    format!("&H{alpha:02X}{}{}{}", hex(5), hex(3), hex(1))
+}
+
+/// `h:mm:ss.cc`
+fn to_ass_time(time: Duration) -> String {
+    let total_secs = time.as_secs();
+    let hours = total_secs / 3600;
+    let minutes = (total_secs % 3600) / 60;
+    let seconds = total_secs % 60;
+    let centis = time.subsec_millis() / 10;
+
+    format!("{hours}:{minutes:02}:{seconds:02}.{centis:02}")
+}
+
+/// Braces start override tags, shown as full-width ones instead
+fn escape(text: &str) -> String {
+    text.replace('{', "｛")
+        .replace('}', "｝")
+        .replace('\n', r"\N")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        timeline::Segment,
+        workspace::{DEFAULT_TEMP_DIR, Workspace},
+    };
+
+    fn segment(text: &str, start: u64, duration: u64, role: SpeakerRole) -> Segment {
+        Segment {
+            text: text.to_owned(),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(duration),
+            words: None,
+            role,
+            emphasis: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn write_styled_subtitle() {
+        let writer = AssSubtitle::new()
+            .with_style(AssStyle::preset("bold").unwrap())
+            .with_colors([(SpeakerRole::Female, "#FF80C0".to_owned())].into());
+
+        let list = vec![
+            segment("记者问{你好}", 0, 1000, SpeakerRole::Anchor),
+            segment("挺好的", 1250, 3725, SpeakerRole::Female),
+        ];
+
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = writer
+            .write_subtitle(&Timeline::new(list, Duration::from_secs(5)), &workspace)
+            .await
+            .unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+
+        assert_eq!(path.extension().unwrap(), "ass");
+        assert!(content.contains("PlayResX: 720\nPlayResY: 1280\n"));
+        assert!(content.contains(
+            "Style: Default,Noto Sans CJK SC,64,&H004DE1FF,&H004DE1FF,&H00000000,&H60000000,-1,0,0,0,100,100,0,0,1,5,2,2,60,60,300,1\n"
+        ));
+        assert!(content.ends_with(
+            r"Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,记者问｛你好｝
+Dialogue: 0,0:00:01.25,0:00:04.97,Default,,0,0,0,,{\c&H00C080FF}挺好的
+"
+        ));
+    }
+
+    #[test]
+    fn boxed_style() {
+        let header = AssSubtitle::new()
+            .with_style(AssStyle::preset("boxed").unwrap())
+            .with_resolution(1080, 1920)
+            .header();
+
+        assert!(header.contains("PlayResX: 1080\nPlayResY: 1920\n"));
+        // the box in the outline colour
+        assert!(header.contains(",&H60000000,&H60000000,0,0,0,0,100,100,0,0,3,10,0,2,"));
+    }
+
+    #[test]
+    fn unknown_preset() {
+        assert!(AssStyle::preset("classic").is_some());
+        assert!(AssStyle::preset("comic").is_none());
+    }
+}
@@ -1,14 +1,126 @@
+pub mod ass;
 pub mod srt;
+pub mod vtt;
 
 use std::{io, path::PathBuf, time::Duration};
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::{
+    timeline::{Segment, Timeline},
+    tts::{WordTiming, align::align_even},
+    workspace::Workspace,
+};
+
 pub type SubtitleResult = Result<PathBuf, SubtitleError>;
 
-pub struct SingleSubtitle {
+/// A short piece of a sentence shown at once, times are from the sentence start
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubtitleChunk {
     pub text: String,
-    pub duration: Duration,
+    pub start: Duration,
+    pub end: Duration,
+    /// the words inside, for highlighting them in sync with speech
+    pub words: Vec<WordTiming>,
+}
+
+/// Punctuation where a chunk should rather break
+const BREAKS: &[char] = &[
+    '，', '。', '！', '？', '；', '：', '、', ',', '.', '!', '?', ';', ':',
+];
+
+/// Punctuation not shown at the end of a chunk
+const TRAILING: &[char] = &['，', '。', '；', '、', ',', '.', ';'];
+
+/// Split the sentence of `segment` into chunks of at most `max_chars` characters
+/// (punctuation not counted), preferring to break at punctuation.
+/// `0` keeps the sentence whole.
+///
+/// Without word timings, the words are spread over the duration by length.
+pub fn chunks(segment: &Segment, max_chars: usize) -> Vec<SubtitleChunk> {
+    let mut words = match &segment.words {
+        Some(words) if !words.is_empty() => words.clone(),
+        _ => align_even(&segment.text, segment.duration),
+    };
+
+    // timings of a provider may be noisy, they must not go back in time or past the sentence
+    let mut last_start = Duration::ZERO;
+    for word in words.iter_mut() {
+        word.start = word.start.clamp(last_start, segment.duration);
+        word.end = word.end.clamp(word.start, segment.duration);
+        last_start = word.start;
+    }
+
+    if max_chars == 0 || words.is_empty() {
+        return vec![SubtitleChunk {
+            text: segment.text.clone(),
+            start: Duration::ZERO,
+            end: segment.duration,
+            words,
+        }];
+    }
+
+    let visible = |word: &WordTiming| word.text.chars().filter(|c| c.is_alphanumeric()).count();
+
+    let mut groups: Vec<Vec<WordTiming>> = vec![];
+    let mut current: Vec<WordTiming> = vec![];
+    let mut len = 0;
+
+    for word in words {
+        let word_len = visible(&word);
+        if !current.is_empty() && len + word_len > max_chars {
+            groups.push(std::mem::take(&mut current));
+            len = 0;
+        }
+
+        let ends_phrase = word.text.trim_end().ends_with(BREAKS);
+        len += word_len;
+        current.push(word);
+
+        // a break at punctuation, unless the chunk would be too short to read
+        if ends_phrase && len * 2 >= max_chars {
+            groups.push(std::mem::take(&mut current));
+            len = 0;
+        }
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+
+    let mut chunks: Vec<SubtitleChunk> = groups
+        .into_iter()
+        .map(|words| {
+            let text: String = words.iter().map(|w| w.text.as_str()).collect();
+            SubtitleChunk {
+                text: text.trim().trim_end_matches(TRAILING).to_owned(),
+                start: words[0].start,
+                end: words[words.len() - 1].end,
+                words,
+            }
+        })
+        .collect();
+
+    // no gap between the chunks of a sentence, the subtitle should not flicker
+    for i in 1..chunks.len() {
+        chunks[i - 1].end = chunks[i].start;
+    }
+    if let Some(last) = chunks.last_mut() {
+        last.end = last.end.max(segment.duration);
+    }
+
+    chunks
+}
+
+/// How the subtitles get into the video
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleMode {
+    /// drawn into the picture
+    #[default]
+    Burn,
+    /// a `mov_text` track the player can turn off, the file is saved beside the video as well
+    Soft,
 }
 
 #[derive(Error, Debug)]
@@ -19,5 +131,100 @@ pub enum SubtitleError {
 
 #[async_trait::async_trait]
 pub trait Subtitle {
-    async fn write_subtitle(&self, subtitles: &Vec<SingleSubtitle>) -> SubtitleResult;
+    /// Write a cue for every segment of `timeline`, at its time in the dubbing
+    async fn write_subtitle(&self, timeline: &Timeline, workspace: &Workspace) -> SubtitleResult;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::news::SpeakerRole;
+
+    fn sentence(text: &str, millis: u64) -> Segment {
+        Segment {
+            text: text.to_owned(),
+            start: Duration::ZERO,
+            duration: Duration::from_millis(millis),
+            words: None,
+            role: SpeakerRole::Anchor,
+            emphasis: false,
+        }
+    }
+
+    fn texts(chunks: &[SubtitleChunk]) -> Vec<&str> {
+        chunks.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn keep_whole_sentence() {
+        let chunks = chunks(&sentence("闺蜜闺蜜想不想玩第五人格", 1200), 0);
+
+        assert_eq!(texts(&chunks), ["闺蜜闺蜜想不想玩第五人格"]);
+        assert_eq!(chunks[0].end, Duration::from_millis(1200));
+    }
+
+    #[test]
+    fn break_at_punctuation() {
+        let chunks = chunks(&sentence("闺蜜闺蜜，想不想玩第五人格喵喵喵。", 3000), 8);
+
+        assert_eq!(texts(&chunks), ["闺蜜闺蜜", "想不想玩第五人格", "喵喵喵"]);
+        // 15 characters in 3s
+        assert_eq!(chunks[0].end, Duration::from_millis(800));
+        assert_eq!(chunks[1].start, Duration::from_millis(800));
+        assert_eq!(chunks[2].end, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn out_of_order_word_timings() {
+        let mut subtitle = sentence("第五人格", 1000);
+        subtitle.words = Some(
+            [
+                ("第", 0, 300),
+                ("五", 600, 200),
+                ("人", 400, 700),
+                ("格", 800, 1200),
+            ]
+            .map(|(text, start, end)| WordTiming {
+                text: text.to_owned(),
+                start: Duration::from_millis(start),
+                end: Duration::from_millis(end),
+            })
+            .to_vec(),
+        );
+
+        let chunks = chunks(&subtitle, 2);
+
+        assert_eq!(texts(&chunks), ["第五", "人格"]);
+        for chunk in &chunks {
+            assert!(chunk.start <= chunk.end);
+        }
+        assert_eq!(chunks[1].start, Duration::from_millis(600));
+        assert_eq!(chunks[1].end, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn follow_word_timings() {
+        let mut subtitle = sentence("第五人格", 2000);
+        subtitle.words = Some(
+            [
+                ("第", 0, 100),
+                ("五", 100, 200),
+                ("人", 1000, 1500),
+                ("格", 1500, 1900),
+            ]
+            .map(|(text, start, end)| WordTiming {
+                text: text.to_owned(),
+                start: Duration::from_millis(start),
+                end: Duration::from_millis(end),
+            })
+            .to_vec(),
+        );
+
+        let chunks = chunks(&subtitle, 2);
+
+        assert_eq!(texts(&chunks), ["第五", "人格"]);
+        assert_eq!(chunks[0].end, Duration::from_millis(1000));
+        assert_eq!(chunks[1].words.len(), 2);
+        assert_eq!(chunks[1].end, Duration::from_secs(2));
+    }
 }
@@ -1,38 +1,46 @@
-use std::{fs, path::PathBuf, time::Duration};
+use std::{collections::BTreeMap, fs, time::Duration};
 
-use crate::subtitle::{SingleSubtitle, Subtitle, SubtitleResult};
-
-const DEFAULT_TEMP_DIR: &str = "./temp";
+use crate::{
+    news::SpeakerRole,
+    subtitle::{Subtitle, SubtitleResult, chunks},
+    timeline::Timeline,
+    workspace::Workspace,
+};
 
 pub struct SrtSubtitle {
-    temp_dir: &'static str,
+    max_chars: usize,
+    /// `#RRGGBB` of the roles, the others keep the default colour
+    colors: BTreeMap<SpeakerRole, String>,
 }
 
 #[async_trait::async_trait]
 impl Subtitle for SrtSubtitle {
-    async fn write_subtitle(&self, subtitles: &Vec<SingleSubtitle>) -> SubtitleResult {
-        let mut content = String::with_capacity(subtitles.len() * 13 * 10);
+    async fn write_subtitle(&self, timeline: &Timeline, workspace: &Workspace) -> SubtitleResult {
+        let mut content = String::with_capacity(timeline.segments.len() * 13 * 10);
 
         let mut index = 1usize;
-        let mut time = Duration::from_secs(0);
-        let gap_time = Duration::from_millis(200);
-
-        for subtitle in subtitles {
-            let (single, end_time) =
-                self.gen_single_subtitle(&subtitle.text, index, time, subtitle.duration);
-
-            content.push_str(&single);
-            time = end_time + gap_time;
-            index += 1;
-        }
 
-        let path = PathBuf::from(self.temp_dir);
-        if !path.exists() {
-            fs::create_dir_all(&path)?;
+        for segment in &timeline.segments {
+            let color = self.colors.get(&segment.role);
+
+            for chunk in chunks(segment, self.max_chars) {
+                let text = match color {
+                    Some(color) => format!(r#"<font color="{color}">{}</font>"#, chunk.text),
+                    None => chunk.text,
+                };
+                let (single, _) = self.gen_single_subtitle(
+                    &text,
+                    index,
+                    segment.start + chunk.start,
+                    chunk.end.saturating_sub(chunk.start),
+                );
+
+                content.push_str(&single);
+                index += 1;
+            }
         }
-        let mut path = path.canonicalize()?;
 
-        path = path.join(format!("{}.srt", nanoid::nanoid!(10).to_string()));
+        let path = workspace.unique_path("subtitle.srt");
 
         fs::write(&path, content)?;
 
@@ -43,10 +51,23 @@ impl Subtitle for SrtSubtitle {
 impl SrtSubtitle {
     pub fn new() -> Self {
         Self {
-            temp_dir: DEFAULT_TEMP_DIR,
+            max_chars: 0,
+            colors: BTreeMap::new(),
         }
     }
 
+    /// Split long sentences into cues of at most `max_chars` characters, `0` keeps them whole
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    /// Colour the subtitles of every speaker role, as `#RRGGBB`
+    pub fn with_colors(mut self, colors: BTreeMap<SpeakerRole, String>) -> Self {
+        self.colors = colors;
+        self
+    }
+
     fn gen_single_subtitle(
         &self,
         text: &str,
@@ -89,28 +110,48 @@ impl SrtSubtitle {
 mod tests {
     use std::{fs, time::Duration};
 
-    use crate::subtitle::{SingleSubtitle, Subtitle, srt::SrtSubtitle};
+    use crate::{
+        news::SpeakerRole,
+        subtitle::{Subtitle, srt::SrtSubtitle},
+        timeline::{Segment, Timeline},
+        workspace::{DEFAULT_TEMP_DIR, Workspace},
+    };
 
     #[tokio::test]
     async fn write_subtitle_success() {
         let writer = SrtSubtitle::new();
 
         let list = vec![
-            SingleSubtitle {
+            Segment {
                 text: "闺蜜闺蜜想不想玩第五人格喵喵喵".to_owned(),
+                start: Duration::ZERO,
                 duration: Duration::from_secs(1),
+                words: None,
+                role: SpeakerRole::Anchor,
+                emphasis: false,
             },
-            SingleSubtitle {
+            Segment {
                 text: "兄弟兄弟想不想玩第五人格喵喵喵".to_owned(),
+                start: Duration::from_millis(1200),
                 duration: Duration::from_secs(1),
+                words: None,
+                role: SpeakerRole::Anchor,
+                emphasis: false,
             },
-            SingleSubtitle {
+            Segment {
                 text: "鸡块狗".to_owned(),
+                start: Duration::from_millis(2400),
                 duration: Duration::from_millis(200),
+                words: None,
+                role: SpeakerRole::Anchor,
+                emphasis: false,
             },
         ];
 
-        let path = writer.write_subtitle(&list).await;
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = writer
+            .write_subtitle(&Timeline::new(list, Duration::from_secs(3)), &workspace)
+            .await;
 
         assert!(path.is_ok());
 
@@ -119,8 +160,6 @@ mod tests {
         assert!(path.exists());
         let content = fs::read_to_string(&path);
 
-        fs::remove_file(path).unwrap();
-
         assert!(content.is_ok());
 
         let content = content.unwrap();
@@ -141,4 +180,95 @@ mod tests {
 "#
         );
     }
+
+    #[tokio::test]
+    async fn write_chunked_subtitle() {
+        let writer = SrtSubtitle::new().with_max_chars(8);
+
+        let list = vec![
+            Segment {
+                text: "闺蜜闺蜜，想不想玩第五人格".to_owned(),
+                start: Duration::ZERO,
+                duration: Duration::from_millis(1200),
+                words: None,
+                role: SpeakerRole::Anchor,
+                emphasis: false,
+            },
+            Segment {
+                text: "鸡块狗".to_owned(),
+                start: Duration::from_millis(1400),
+                duration: Duration::from_millis(300),
+                words: None,
+                role: SpeakerRole::Anchor,
+                emphasis: false,
+            },
+        ];
+
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = writer
+            .write_subtitle(&Timeline::new(list, Duration::from_secs(3)), &workspace)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"1
+00:00:00,000 --> 00:00:00,400
+闺蜜闺蜜
+
+2
+00:00:00,400 --> 00:00:01,200
+想不想玩第五人格
+
+3
+00:00:01,400 --> 00:00:01,700
+鸡块狗
+
+"#
+        );
+    }
+
+    #[tokio::test]
+    async fn color_by_role() {
+        let writer =
+            SrtSubtitle::new().with_colors([(SpeakerRole::Female, "#FF80C0".to_owned())].into());
+
+        let list = vec![
+            Segment {
+                text: "记者问".to_owned(),
+                start: Duration::ZERO,
+                duration: Duration::from_millis(600),
+                words: None,
+                role: SpeakerRole::Anchor,
+                emphasis: false,
+            },
+            Segment {
+                text: "挺好的".to_owned(),
+                start: Duration::from_millis(800),
+                duration: Duration::from_millis(600),
+                words: None,
+                role: SpeakerRole::Female,
+                emphasis: false,
+            },
+        ];
+
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = writer
+            .write_subtitle(&Timeline::new(list, Duration::from_secs(3)), &workspace)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r##"1
+00:00:00,000 --> 00:00:00,600
+记者问
+
+2
+00:00:00,800 --> 00:00:01,400
+<font color="#FF80C0">挺好的</font>
+
+"##
+        );
+    }
 }
@@ -0,0 +1,165 @@
+use std::{collections::BTreeMap, fmt::Write, fs, time::Duration};
+
+use crate::{
+    news::SpeakerRole,
+    subtitle::{Subtitle, SubtitleResult, chunks},
+    timeline::Timeline,
+    workspace::Workspace,
+};
+
+/// WebVTT captions, for platforms taking a caption file beside the video
+pub struct VttSubtitle {
+    max_chars: usize,
+    /// `#RRGGBB` of the roles, as cue classes styled in the file
+    colors: BTreeMap<SpeakerRole, String>,
+}
+
+#[async_trait::async_trait]
+impl Subtitle for VttSubtitle {
+    async fn write_subtitle(&self, timeline: &Timeline, workspace: &Workspace) -> SubtitleResult {
+        let mut content = String::from("WEBVTT\n\n");
+
+        if !self.colors.is_empty() {
+            content.push_str("STYLE\n");
+            for (role, color) in &self.colors {
+                let _ = writeln!(content, "::cue(.{}) {{ color: {color}; }}", class(*role));
+            }
+            content.push('\n');
+        }
+
+        for segment in &timeline.segments {
+            let colored = self.colors.contains_key(&segment.role);
+
+            for chunk in chunks(segment, self.max_chars) {
+                let text = escape(&chunk.text);
+                let text = if colored {
+                    format!("<c.{}>{text}</c>", class(segment.role))
+                } else {
+                    text
+                };
+
+                let _ = write!(
+                    content,
+                    "{} --> {}\n{text}\n\n",
+                    to_vtt_time(segment.start + chunk.start),
+                    to_vtt_time(segment.start + chunk.end),
+                );
+            }
+        }
+
+        let path = workspace.unique_path("subtitle.vtt");
+
+        fs::write(&path, content)?;
+
+        Ok(path)
+    }
+}
+
+impl Default for VttSubtitle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VttSubtitle {
+    pub fn new() -> Self {
+        Self {
+            max_chars: 0,
+            colors: BTreeMap::new(),
+        }
+    }
+
+    /// Split long sentences into cues of at most `max_chars` characters, `0` keeps them whole
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    /// Colour the subtitles of every speaker role, as `#RRGGBB`
+    pub fn with_colors(mut self, colors: BTreeMap<SpeakerRole, String>) -> Self {
+        self.colors = colors;
+        self
+    }
+}
+
+fn class(role: SpeakerRole) -> String {
+    format!("{role:?}").to_lowercase()
+}
+
+/// `hh:mm:ss.ttt`
+fn to_vtt_time(time: Duration) -> String {
+    let total_secs = time.as_secs();
+    let hours = total_secs / 3600;
+    let minutes = (total_secs % 3600) / 60;
+    let seconds = total_secs % 60;
+    let millis = time.subsec_millis();
+
+    format!("{hours:02}:{minutes:02}:{seconds:02}.{millis:03}")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        timeline::Segment,
+        workspace::{DEFAULT_TEMP_DIR, Workspace},
+    };
+
+    #[tokio::test]
+    async fn write_vtt() {
+        let writer = VttSubtitle::new()
+            .with_max_chars(8)
+            .with_colors([(SpeakerRole::Female, "#FF80C0".to_owned())].into());
+
+        let list = vec![
+            Segment {
+                text: "闺蜜闺蜜，想不想玩第五人格".to_owned(),
+                start: Duration::ZERO,
+                duration: Duration::from_millis(1200),
+                words: None,
+                role: SpeakerRole::Anchor,
+                emphasis: false,
+            },
+            Segment {
+                text: "<挺好的>".to_owned(),
+                start: Duration::from_millis(1400),
+                duration: Duration::from_millis(600),
+                words: None,
+                role: SpeakerRole::Female,
+                emphasis: false,
+            },
+        ];
+
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = writer
+            .write_subtitle(&Timeline::new(list, Duration::from_secs(3)), &workspace)
+            .await
+            .unwrap();
+
+        assert_eq!(path.extension().unwrap(), "vtt");
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"WEBVTT
+
+STYLE
+::cue(.female) { color: #FF80C0; }
+
+00:00:00.000 --> 00:00:00.400
+闺蜜闺蜜
+
+00:00:00.400 --> 00:00:01.200
+想不想玩第五人格
+
+00:00:01.400 --> 00:00:02.000
+<c.female>&lt;挺好的&gt;</c>
+
+"#
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use crate::{
+    audio::assemble::ClipOffset,
+    news::{Sentence, SpeakerRole},
+    tts::{TTSFile, WordTiming, align::shift_timings},
+};
+
+/// A sentence and when it is spoken in the dubbing
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub text: String,
+    /// when the sentence starts in the dubbing
+    pub start: Duration,
+    pub duration: Duration,
+    /// word timings from the sentence start, if known
+    pub words: Option<Vec<WordTiming>>,
+    /// who speaks it, for colouring the subtitle
+    pub role: SpeakerRole,
+    /// marked by the extractor, stressed by a sound effect
+    pub emphasis: bool,
+}
+
+impl Segment {
+    pub fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+}
+
+/// When every sentence is spoken, made once from the assembled dubbing.
+///
+/// The audio mixing, the subtitles, the video editor and the final composition
+/// all take their times from it, so they cannot drift apart.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timeline {
+    /// in order, without overlap
+    pub segments: Vec<Segment>,
+    /// length of the video, the pause after the last sentence included
+    pub duration: Duration,
+}
+
+impl Timeline {
+    pub fn new(segments: Vec<Segment>, duration: Duration) -> Self {
+        Self { segments, duration }
+    }
+
+    /// A timeline without speech, for a video without dubbing
+    pub fn silent(duration: Duration) -> Self {
+        Self::new(vec![], duration)
+    }
+
+    /// The sentences at the offsets of their clips in the dubbing.
+    /// The word timings of the clips move with the silence cut from them,
+    /// the sentences are cut at `duration`.
+    pub fn from_dubbing(
+        sentences: &[Sentence],
+        tts_files: Vec<TTSFile>,
+        offsets: &[ClipOffset],
+        duration: Duration,
+    ) -> Self {
+        let segments = tts_files
+            .into_iter()
+            .zip(sentences)
+            .zip(offsets)
+            .filter(|(_, offset)| offset.start < duration)
+            .map(|((tts, sentence), offset)| {
+                let length = offset.duration.min(duration - offset.start);
+                let words = tts.words.map(|mut words| {
+                    shift_timings(&mut words, offset.trimmed, length);
+                    words
+                });
+
+                Segment {
+                    text: tts.text,
+                    start: offset.start,
+                    duration: length,
+                    words,
+                    role: sentence.role,
+                    emphasis: sentence.emphasis,
+                }
+            })
+            .collect();
+
+        Self::new(segments, duration)
+    }
+
+    /// Starts of the sentences marked as emphasis
+    pub fn emphasis(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.segments
+            .iter()
+            .filter(|segment| segment.emphasis)
+            .map(|segment| segment.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn segments_at_offsets() {
+        let sentences = [
+            Sentence::new("你好"),
+            Sentence::new("再见").with_emphasis(true),
+        ];
+        let tts_files = sentences
+            .iter()
+            .map(|sentence| TTSFile {
+                path: PathBuf::new(),
+                text: sentence.text.clone(),
+                duration: millis(600),
+                words: Some(vec![WordTiming {
+                    text: sentence.text.clone(),
+                    start: millis(100),
+                    end: millis(500),
+                }]),
+            })
+            .collect();
+        let offsets = [
+            ClipOffset {
+                start: millis(0),
+                duration: millis(400),
+                trimmed: millis(100),
+            },
+            ClipOffset {
+                start: millis(700),
+                duration: millis(600),
+                trimmed: Duration::ZERO,
+            },
+        ];
+
+        let timeline = Timeline::from_dubbing(&sentences, tts_files, &offsets, millis(1600));
+
+        assert_eq!(timeline.duration, millis(1600));
+        assert_eq!(timeline.segments[1].start, millis(700));
+        assert_eq!(timeline.segments[1].end(), millis(1300));
+        let words = timeline.segments[0].words.as_ref().unwrap();
+        assert_eq!((words[0].start, words[0].end), (millis(0), millis(400)));
+        assert_eq!(timeline.emphasis().collect::<Vec<_>>(), [millis(700)]);
+    }
+
+    #[test]
+    fn cut_at_duration() {
+        let sentences = [Sentence::new("你好"), Sentence::new("再见")];
+        let tts_files = sentences
+            .iter()
+            .map(|sentence| TTSFile {
+                path: PathBuf::new(),
+                text: sentence.text.clone(),
+                duration: millis(600),
+                words: None,
+            })
+            .collect();
+        let offsets = [
+            ClipOffset {
+                start: millis(0),
+                duration: millis(600),
+                trimmed: Duration::ZERO,
+            },
+            ClipOffset {
+                start: millis(700),
+                duration: millis(600),
+                trimmed: Duration::ZERO,
+            },
+        ];
+
+        let timeline = Timeline::from_dubbing(&sentences, tts_files, &offsets, millis(500));
+
+        assert_eq!(timeline.segments.len(), 1);
+        assert_eq!(timeline.segments[0].end(), millis(500));
+    }
+}
@@ -1,18 +1,143 @@
-use std::{fs, path::PathBuf, time::Duration};
+use std::time::Duration;
 
 use hound::WavReader;
 use reqwest::{Client, header};
 use serde::{Deserialize, Serialize};
 
-use crate::tts::{TTSError, TTSFile, TTSService};
+use crate::{
+    retry::{Failure, RetryPolicy},
+    tts::{
+        TTSError, TTSFile, TTSService,
+        limiter::{RateLimiter, SynthesisLimits, synthesize_ordered},
+    },
+    workspace::Workspace,
+};
+
+pub const DEFAULT_MODEL: &str = "qwen-tts";
+pub const DEFAULT_VOICE: &str = "Serena";
+
+/// Voice and speaking parameters of `AliTTS`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AliTTSOptions {
+    pub model: String,
+    pub voice: String,
+    /// speech rate, 0.5 ~ 2.0, 1.0 is normal
+    pub rate: f32,
+    /// pitch, 0.5 ~ 2.0, 1.0 is normal
+    pub pitch: f32,
+    /// volume, 0 ~ 100
+    pub volume: u32,
+}
+
+impl Default for AliTTSOptions {
+    fn default() -> Self {
+        Self {
+            model: DEFAULT_MODEL.to_owned(),
+            voice: DEFAULT_VOICE.to_owned(),
+            rate: 1.0,
+            pitch: 1.0,
+            volume: 50,
+        }
+    }
+}
+
+impl AliTTSOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.model.trim().is_empty() {
+            return Err("model cannot be empty".to_owned());
+        }
+        if self.voice.trim().is_empty() {
+            return Err("voice cannot be empty".to_owned());
+        }
+        if !(0.5..=2.0).contains(&self.rate) {
+            return Err(format!("rate must be in 0.5 ~ 2.0, got {}", self.rate));
+        }
+        if !(0.5..=2.0).contains(&self.pitch) {
+            return Err(format!("pitch must be in 0.5 ~ 2.0, got {}", self.pitch));
+        }
+        if self.volume > 100 {
+            return Err(format!("volume must be in 0 ~ 100, got {}", self.volume));
+        }
+
+        Ok(())
+    }
+}
+
+/// A voice Ali TTS provides
+#[derive(Clone, Debug, Serialize)]
+pub struct AliVoice {
+    /// the value of `voice` in request
+    pub id: &'static str,
+    pub name: &'static str,
+    pub gender: &'static str,
+    /// tone of the voice, helps to match the voice to the story
+    pub description: &'static str,
+    /// models supporting this voice
+    pub models: &'static [&'static str],
+}
 
-const DEFAULT_TEMP_DIR: &str = "temp";
+/// Voices of the Qwen TTS models
+pub const VOICES: &[AliVoice] = &[
+    AliVoice {
+        id: "Cherry",
+        name: "芊悦",
+        gender: "female",
+        description: "阳光积极、亲切自然",
+        models: &["qwen-tts", "qwen-tts-latest"],
+    },
+    AliVoice {
+        id: "Serena",
+        name: "苏瑶",
+        gender: "female",
+        description: "温柔知性",
+        models: &["qwen-tts", "qwen-tts-latest"],
+    },
+    AliVoice {
+        id: "Ethan",
+        name: "晨煦",
+        gender: "male",
+        description: "阳光温暖、活力朝气",
+        models: &["qwen-tts", "qwen-tts-latest"],
+    },
+    AliVoice {
+        id: "Chelsie",
+        name: "千雪",
+        gender: "female",
+        description: "二次元虚拟女友",
+        models: &["qwen-tts", "qwen-tts-latest"],
+    },
+    AliVoice {
+        id: "Dylan",
+        name: "北京-晓东",
+        gender: "male",
+        description: "北京胡同少年，京腔",
+        models: &["qwen-tts-latest"],
+    },
+    AliVoice {
+        id: "Jada",
+        name: "上海-阿珍",
+        gender: "female",
+        description: "风风火火的沪上阿姐，上海话",
+        models: &["qwen-tts-latest"],
+    },
+    AliVoice {
+        id: "Sunny",
+        name: "四川-晴儿",
+        gender: "female",
+        description: "甜到你心里的川妹子，四川话",
+        models: &["qwen-tts-latest"],
+    },
+];
 
 pub struct AliTTS {
     http: Client,
     url: String,
     key: String,
-    temp_dir: String,
+    options: AliTTSOptions,
+    limits: SynthesisLimits,
+    limiter: RateLimiter,
+    retry: RetryPolicy,
 }
 
 impl AliTTS {
@@ -20,100 +145,173 @@ impl AliTTS {
     /// url: The API endpoint for the Ali TTS service.
     /// key: The ALI API key for authentication.
     pub fn new(url: String, key: String) -> Self {
+        let limits = SynthesisLimits::default();
         Self {
             http: Client::new(),
             url,
             key,
-            temp_dir: DEFAULT_TEMP_DIR.to_owned(),
+            options: AliTTSOptions::default(),
+            limiter: RateLimiter::new(limits.requests_per_second),
+            limits,
+            retry: RetryPolicy::default(),
         }
     }
+
+    pub fn with_options(mut self, options: AliTTSOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: SynthesisLimits) -> Self {
+        self.limiter = RateLimiter::new(limits.requests_per_second);
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Share a `Client`, so its connection pool is reused by every job
+    pub fn with_client(mut self, http: Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    async fn synthesize(
+        &self,
+        index: usize,
+        text: &str,
+        workspace: &Workspace,
+    ) -> Result<TTSFile, TTSError> {
+        let body = ApiRequest::new(&self.options, text);
+
+        // synthesis has no side effect, it is safe to request again
+        let data: ApiResponse = self
+            .retry
+            .run(|| async {
+                let response = self
+                    .http
+                    .post(&self.url)
+                    .header(header::AUTHORIZATION, format!("Bearer {}", self.key))
+                    .json(&body)
+                    .send()
+                    .await?;
+
+                let status = response.status();
+                if !status.is_success() {
+                    let data = response.text().await.unwrap_or_default();
+                    return Err(Failure::from_status(status, &data));
+                }
+
+                response
+                    .json()
+                    .await
+                    .map_err(|e| Failure::Permanent(e.to_string()))
+            })
+            .await
+            .map_err(|e| TTSError::Network(format!("request ali tts failed: {e}")))?;
+
+        let audio_url = data.output.audio.url;
+
+        let bytes = self
+            .retry
+            .run(|| async {
+                let response = self.http.get(&audio_url).send().await?;
+
+                let status = response.status();
+                if !status.is_success() {
+                    return Err(Failure::from_status(status, ""));
+                }
+
+                Ok(response.bytes().await?)
+            })
+            .await
+            .map_err(|e| TTSError::Network(format!("download ali tts audio failed: {e}")))?;
+
+        let file = workspace.unique_path(&format!("voice_{:03}.wav", index + 1));
+        tokio::fs::write(&file, bytes).await?;
+        let reader = WavReader::open(&file).map_err(|e| TTSError::HandleFailed(e.to_string()))?;
+
+        let seconds = reader.duration() as f64 / reader.spec().sample_rate as f64;
+        let duration = Duration::from_secs_f64(seconds);
+
+        Ok(TTSFile {
+            path: file,
+            text: text.to_owned(),
+            duration,
+            words: None,
+        })
+    }
 }
 
 #[async_trait::async_trait]
 impl TTSService for AliTTS {
-    async fn tts(&self, text_list: &Vec<String>) -> Result<Vec<TTSFile>, TTSError> {
-        let mut list = vec![];
-
-        let to_network_err = |e: reqwest::Error| TTSError::Network(e.to_string());
-
-        let tmp_path = PathBuf::from(&self.temp_dir);
-        fs::create_dir_all(&tmp_path).map_err(|e| TTSError::HandleFailed(e.to_string()))?;
-
-        let tmp_path = tmp_path
-            .canonicalize()
-            .map_err(|e| TTSError::HandleFailed(e.to_string()))?;
-
-        let mut index = 1usize;
-
-        for text in text_list {
-            let body = build_body(text);
-            let response = self
-                .http
-                .post(&self.url)
-                .header(header::AUTHORIZATION, format!("Bearer {}", self.key))
-                .header(header::CONTENT_TYPE, "application/json")
-                .body(body)
-                .send()
-                .await
-                .map_err(to_network_err)?;
-
-            if response.status() != 200 {
-                let data = response.text().await.unwrap();
-                return Err(TTSError::Network(format!(
-                    "request ali tts failed: {}",
-                    data
-                )));
-            }
-
-            let data: ApiResponse = response.json().await.map_err(to_network_err)?;
-
-            let audio_url = data.output.audio.url;
-
-            let response = self
-                .http
-                .get(audio_url)
-                .send()
-                .await
-                .map_err(to_network_err)?;
-            let bytes = response.bytes().await.map_err(to_network_err)?;
-
-            let file = tmp_path.clone().join(format!("voice_{:03}.wav", index));
-            index += 1;
-            fs::write(&file, bytes)?;
-            let reader =
-                WavReader::open(&file).map_err(|e| TTSError::HandleFailed(e.to_string()))?;
-
-            let seconds = reader.duration() as f64 / reader.spec().sample_rate as f64;
-            let duration = Duration::from_secs_f64(seconds);
-
-            list.push(TTSFile {
-                path: file,
-                text: text.clone(),
-                duration,
-            });
-        }
+    async fn tts(
+        &self,
+        text_list: &Vec<String>,
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        let tasks = text_list
+            .iter()
+            .enumerate()
+            .map(|(index, text)| self.synthesize(index, text, workspace))
+            .collect();
+
+        synthesize_ordered(tasks, &self.limits, &self.limiter).await
+    }
 
-        fn build_body(text: &String) -> String {
-            let len = text.chars().count();
-            let mut body = String::with_capacity(100 + len);
-
-            body.push_str(
-                r#"{
-    "model": "qwen-tts",
-    "input": {
-        "text": ""#,
-            );
-            body.push_str(&text);
-            body.push_str(
-                r#"",
-        "voice": "Serena"
-    }"#,
-            );
-
-            body
-        }
+    fn fingerprint(&self) -> Option<String> {
+        let AliTTSOptions {
+            model,
+            voice,
+            rate,
+            pitch,
+            volume,
+        } = &self.options;
+
+        Some(format!(
+            "ali:{model}:{voice}:rate={rate}:pitch={pitch}:volume={volume}"
+        ))
+    }
+}
 
-        Ok(list)
+/// Request body of the DashScope multimodal generation API
+#[derive(Debug, Serialize)]
+struct ApiRequest<'a> {
+    pub model: &'a str,
+    pub input: ApiInput<'a>,
+    pub parameters: ApiParameters,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiInput<'a> {
+    pub text: &'a str,
+    pub voice: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiParameters {
+    pub rate: f32,
+    pub pitch: f32,
+    pub volume: u32,
+}
+
+impl<'a> ApiRequest<'a> {
+    fn new(options: &'a AliTTSOptions, text: &'a str) -> Self {
+        Self {
+            model: &options.model,
+            input: ApiInput {
+                text,
+                voice: &options.voice,
+            },
+            parameters: ApiParameters {
+                rate: options.rate,
+                pitch: options.pitch,
+                volume: options.volume,
+            },
+        }
     }
 }
 
@@ -161,6 +359,7 @@ struct ApiResponse {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::workspace::DEFAULT_TEMP_DIR;
 
     #[tokio::test]
     async fn test_wav_result_one() {
@@ -185,7 +384,7 @@ mod tests {
 
         // Create a mock
         let _mock = server
-            .mock("GET", "/first")
+            .mock("POST", "/first")
             .with_status(200)
             .with_header(header::CONTENT_TYPE, "application/json")
             .with_body(first_body)
@@ -199,7 +398,8 @@ mod tests {
             .create();
 
         let ali_tts = AliTTS::new(format!("{}/first", url), "test_key".to_string());
-        let tts_files = ali_tts.tts(&vec!["测试".to_owned()]).await;
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts_files = ali_tts.tts(&vec!["测试".to_owned()], &workspace).await;
 
         assert!(tts_files.is_ok());
         let tts_files = tts_files.unwrap();
@@ -232,7 +432,7 @@ mod tests {
 
         // Create a mock
         let _mock = server
-            .mock("GET", "/first")
+            .mock("POST", "/first")
             .with_status(200)
             .with_header(header::CONTENT_TYPE, "application/json")
             .with_body(first_body)
@@ -246,8 +446,9 @@ mod tests {
             .create();
 
         let ali_tts = AliTTS::new(format!("{}/first", url), "test_key".to_string());
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
         let tts_files = ali_tts
-            .tts(&vec!["测试".to_owned(), "测试".to_owned()])
+            .tts(&vec!["测试".to_owned(), "测试".to_owned()], &workspace)
             .await;
 
         assert!(tts_files.is_ok());
@@ -259,4 +460,42 @@ mod tests {
         assert_eq!(tts_files[1].text, "测试");
         assert!(tts_files[1].path.exists());
     }
+
+    #[test]
+    fn request_escapes_text() {
+        let options = AliTTSOptions::default();
+        let text = "他说：\"你好\\世界\"\n\t😀 end";
+
+        let body = serde_json::to_string(&ApiRequest::new(&options, text)).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(value["input"]["text"], text);
+        assert_eq!(value["input"]["voice"], options.voice);
+        assert_eq!(value["model"], options.model);
+        assert_eq!(value["parameters"]["volume"], options.volume);
+    }
+
+    #[tokio::test]
+    async fn request_sends_json_body() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let text = "引号\"、反斜杠\\和表情🎉";
+
+        let mock = server
+            .mock("POST", "/first")
+            .match_header(header::CONTENT_TYPE, "application/json")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "input": { "text": text }
+            })))
+            .with_status(400)
+            .with_body("rejected")
+            .create();
+
+        let ali_tts = AliTTS::new(format!("{url}/first"), "test_key".to_string());
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let result = ali_tts.tts(&vec![text.to_owned()], &workspace).await;
+
+        mock.assert();
+        assert!(matches!(result, Err(TTSError::Network(_))));
+    }
 }
@@ -0,0 +1,246 @@
+use std::{path::Path, time::Duration};
+
+use crate::{
+    audio::Audio,
+    tts::{TTSError, WordTiming},
+};
+
+/// Length of an analysis frame
+const FRAME_MS: u64 = 10;
+
+/// A frame is voiced above this ratio of the loudest frame
+const VOICED_RATIO: f32 = 0.05;
+
+/// A word to be timed, weighted by how long it roughly takes to speak
+struct Unit {
+    text: String,
+    weight: f64,
+}
+
+/// Split `text` into words: every CJK character is a word, so is every run of ASCII letters and digits.
+/// Punctuation and whitespace stick to the word before them and take no time.
+fn split_units(text: &str) -> Vec<Unit> {
+    fn flush(ascii: &mut String, units: &mut Vec<Unit>) {
+        if !ascii.is_empty() {
+            // about 3 letters take as long as a Chinese character
+            let weight = (ascii.len() as f64 / 3.0).max(1.0);
+            units.push(Unit {
+                text: std::mem::take(ascii),
+                weight,
+            });
+        }
+    }
+
+    let mut units: Vec<Unit> = vec![];
+    let mut ascii = String::new();
+
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            ascii.push(c);
+            continue;
+        }
+        flush(&mut ascii, &mut units);
+
+        if c.is_alphanumeric() {
+            units.push(Unit {
+                text: c.to_string(),
+                weight: 1.0,
+            });
+        } else if let Some(last) = units.last_mut() {
+            last.text.push(c);
+        } else {
+            units.push(Unit {
+                text: c.to_string(),
+                weight: 0.0,
+            });
+        }
+    }
+    flush(&mut ascii, &mut units);
+
+    units
+}
+
+/// Give every unit its share of the speech, `at(start, end)` maps the
+/// fractions of the total weight onto the clip
+fn distribute(units: Vec<Unit>, at: impl Fn(f64, f64) -> (Duration, Duration)) -> Vec<WordTiming> {
+    let total: f64 = units.iter().map(|u| u.weight).sum();
+    if total == 0.0 {
+        return vec![];
+    }
+
+    let mut done = 0.0;
+    units
+        .into_iter()
+        .map(|unit| {
+            let from = done / total;
+            done += unit.weight;
+            let (start, end) = at(from, done / total);
+
+            WordTiming {
+                text: unit.text,
+                start,
+                end,
+            }
+        })
+        .collect()
+}
+
+/// Spread the words of `text` evenly over `duration`, weighted by their length
+pub fn align_even(text: &str, duration: Duration) -> Vec<WordTiming> {
+    distribute(split_units(text), |from, to| {
+        (duration.mul_f64(from), duration.mul_f64(to))
+    })
+}
+
+/// Fallback alignment of `text` to its audio, for providers without timestamps.
+///
+/// This is not a phonetic forced alignment: silent frames are found by their energy
+/// and the words are spread over the voiced frames only, so the pauses between
+/// phrases do not shift the words after them.
+pub fn align_audio(text: &str, path: &Path) -> Result<Vec<WordTiming>, TTSError> {
+    let frames = frame_energy(path)?;
+    let peak = frames.iter().cloned().fold(0.0f32, f32::max);
+    let frame = Duration::from_millis(FRAME_MS);
+
+    let voiced: Vec<usize> = frames
+        .iter()
+        .enumerate()
+        .filter(|(_, rms)| peak > 0.0 && **rms >= peak * VOICED_RATIO)
+        .map(|(i, _)| i)
+        .collect();
+
+    if voiced.is_empty() {
+        return Ok(align_even(text, frame * frames.len() as u32));
+    }
+
+    let count = voiced.len() as f64;
+    let last = voiced.len() - 1;
+    // the position inside a voiced frame, the start is in the frame after a boundary,
+    // the end in the frame before it, so no word spans a pause
+    let start_at = |pos: f64| {
+        let k = (pos.floor() as usize).min(last);
+        frame * voiced[k] as u32 + frame.mul_f64((pos - k as f64).clamp(0.0, 1.0))
+    };
+    let end_at = |pos: f64| {
+        let k = (pos.ceil() as usize).saturating_sub(1).min(last);
+        frame * voiced[k] as u32 + frame.mul_f64((pos - k as f64).clamp(0.0, 1.0))
+    };
+
+    Ok(distribute(split_units(text), |from, to| {
+        let start = start_at(from * count);
+        (start, end_at(to * count).max(start))
+    }))
+}
+
+/// RMS of every frame, all channels together
+fn frame_energy(path: &Path) -> Result<Vec<f32>, TTSError> {
+    let audio = Audio::read(path).map_err(|e| TTSError::HandleFailed(e.to_string()))?;
+
+    let frame_len =
+        ((audio.sample_rate as u64 * FRAME_MS / 1000) as usize * audio.channels as usize).max(1);
+
+    Ok(audio
+        .samples
+        .chunks(frame_len)
+        .map(|frame| (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt())
+        .collect())
+}
+
+/// Stretch the timings of a clip whose speed was changed, e.g. by an audio effect
+pub fn scale_timings(words: &mut [WordTiming], from: Duration, to: Duration) {
+    if from.is_zero() {
+        return;
+    }
+
+    let ratio = to.as_secs_f64() / from.as_secs_f64();
+    for word in words {
+        word.start = word.start.mul_f64(ratio);
+        word.end = word.end.mul_f64(ratio);
+    }
+}
+
+/// Move the timings back by the silence cut from the start of a clip,
+/// keeping them inside its new `duration`
+pub fn shift_timings(words: &mut [WordTiming], trimmed: Duration, duration: Duration) {
+    for word in words {
+        word.start = word.start.saturating_sub(trimmed).min(duration);
+        word.end = word.end.saturating_sub(trimmed).min(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use super::*;
+    use crate::workspace::{DEFAULT_TEMP_DIR, Workspace};
+
+    fn texts(words: &[WordTiming]) -> Vec<&str> {
+        words.iter().map(|w| w.text.as_str()).collect()
+    }
+
+    #[test]
+    fn split_chinese_and_ascii() {
+        let words = align_even("你好，AI新闻!", Duration::from_secs(4));
+
+        assert_eq!(texts(&words), ["你", "好，", "AI", "新", "闻!"]);
+        assert_eq!(words[0].start, Duration::ZERO);
+        assert_eq!(words[1].end, Duration::from_millis(1600));
+        assert_eq!(words[4].end, Duration::from_secs(4));
+    }
+
+    #[test]
+    fn punctuation_only() {
+        assert!(align_even("。。。", Duration::from_secs(1)).is_empty());
+    }
+
+    #[test]
+    fn scale() {
+        let mut words = align_even("一二", Duration::from_secs(2));
+        scale_timings(&mut words, Duration::from_secs(2), Duration::from_secs(1));
+
+        assert_eq!(words[1].start, Duration::from_millis(500));
+        assert_eq!(words[1].end, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn shift() {
+        let mut words = align_even("一二", Duration::from_secs(2));
+        shift_timings(
+            &mut words,
+            Duration::from_millis(500),
+            Duration::from_millis(1200),
+        );
+
+        assert_eq!(words[0].start, Duration::ZERO);
+        assert_eq!(words[0].end, Duration::from_millis(500));
+        assert_eq!(words[1].end, Duration::from_millis(1200));
+    }
+
+    #[test]
+    fn skip_the_pause() {
+        // 0.4s tone, 0.6s silence, 0.4s tone
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let path = workspace.path("pause.wav");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..11_200 {
+            let voiced = !(3200..8000).contains(&i);
+            let sample = if voiced && i % 16 < 8 { 8000i16 } else { 0 };
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let words = align_audio("第一，第二", &path).unwrap();
+
+        assert_eq!(texts(&words), ["第", "一，", "第", "二"]);
+        assert_eq!(words[1].end, Duration::from_millis(400));
+        assert_eq!(words[2].start, Duration::from_millis(1000));
+        assert_eq!(words[3].end, Duration::from_millis(1400));
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::{
+    tts::{TTSError, TTSFile, TTSService, get_wav_len},
+    workspace::Workspace,
+};
+
+pub const DEFAULT_CACHE_DIR: &str = "./cache/tts";
+
+/// Config of the TTS audio cache
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TTSCacheConfig {
+    pub enabled: bool,
+    pub dir: PathBuf,
+    /// the least recently used audio is evicted above this size
+    pub max_size_mb: u64,
+}
+
+impl Default for TTSCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: PathBuf::from(DEFAULT_CACHE_DIR),
+            max_size_mb: 512,
+        }
+    }
+}
+
+/// Caches the audio of any `TTSService`, so re-rendering a story does not re-bill every sentence.
+///
+/// Audio is keyed by the SHA-256 of the service [`TTSService::fingerprint`] and the text,
+/// services without fingerprint are never cached.
+/// A hit is copied into the job workspace, the cache directory is only touched by this type.
+pub struct CachedTTS<T> {
+    inner: T,
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl<T: TTSService + Send + Sync> CachedTTS<T> {
+    pub fn new(inner: T, config: &TTSCacheConfig) -> Self {
+        Self {
+            inner,
+            dir: config.dir.clone(),
+            max_bytes: config.max_size_mb.saturating_mul(1024 * 1024),
+        }
+    }
+
+    /// Cache key of `text` synthesised by a service with `fingerprint`
+    pub fn key(fingerprint: &str, text: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(fingerprint.as_bytes());
+        // separates the fields, neither contains a NUL
+        hasher.update([0]);
+        hasher.update(text.as_bytes());
+
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn cache_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.wav"))
+    }
+
+    async fn lookup(&self, key: &str, text: &str, workspace: &Workspace) -> Option<TTSFile> {
+        let cached = self.cache_path(key);
+        if !cached.exists() {
+            return None;
+        }
+
+        let path = workspace.unique_path(&format!("{key}.wav"));
+        fs::copy(&cached, &path).await.ok()?;
+        // a broken file is a miss, it is overwritten by the new audio
+        let duration = get_wav_len(&path).await.ok()?;
+        touch(&cached);
+
+        Some(TTSFile {
+            path,
+            text: text.to_owned(),
+            duration,
+            words: None,
+        })
+    }
+
+    async fn store(&self, key: &str, file: &TTSFile) -> Result<(), TTSError> {
+        fs::create_dir_all(&self.dir).await?;
+
+        // concurrent jobs may store the same key, the rename keeps the file whole
+        let cached = self.cache_path(key);
+        let partial = self.dir.join(format!("{key}.{}.part", nanoid::nanoid!(6)));
+        fs::copy(&file.path, &partial).await?;
+        fs::rename(&partial, &cached).await?;
+
+        Ok(())
+    }
+
+    /// Remove the least recently used audio until the cache fits `max_bytes`
+    async fn evict(&self) -> Result<(), TTSError> {
+        let mut entries = vec![];
+        let mut total = 0;
+
+        let mut dir = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "wav") {
+                continue;
+            }
+
+            let meta = entry.metadata().await?;
+            let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            total += meta.len();
+            entries.push((used, meta.len(), path));
+        }
+
+        entries.sort_by_key(|(used, _, _)| *used);
+
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).await.is_ok() {
+                total -= len;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: TTSService + Send + Sync> TTSService for CachedTTS<T> {
+    async fn tts(
+        &self,
+        text_list: &Vec<String>,
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        let Some(fingerprint) = self.inner.fingerprint() else {
+            return self.inner.tts(text_list, workspace).await;
+        };
+
+        let keys: Vec<String> = text_list
+            .iter()
+            .map(|text| Self::key(&fingerprint, text))
+            .collect();
+
+        let mut files = Vec::with_capacity(text_list.len());
+        let mut misses = vec![];
+        for (i, (key, text)) in keys.iter().zip(text_list).enumerate() {
+            let hit = self.lookup(key, text, workspace).await;
+            if hit.is_none() {
+                misses.push(i);
+            }
+            files.push(hit);
+        }
+
+        if !misses.is_empty() {
+            let miss_texts: Vec<String> = misses.iter().map(|i| text_list[*i].clone()).collect();
+            let synthesized = self.inner.tts(&miss_texts, workspace).await?;
+
+            // the audio cannot be matched to the sentences, the hits would lose their place
+            if synthesized.len() != miss_texts.len() {
+                return Err(TTSError::HandleFailed(format!(
+                    "{} clips synthesised for {} sentences",
+                    synthesized.len(),
+                    miss_texts.len()
+                )));
+            }
+
+            for (i, file) in misses.into_iter().zip(synthesized) {
+                self.store(&keys[i], &file).await?;
+                files[i] = Some(file);
+            }
+
+            self.evict().await?;
+        }
+
+        Ok(files.into_iter().flatten().collect())
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        self.inner.fingerprint()
+    }
+
+    fn accepts_ssml(&self) -> bool {
+        self.inner.accepts_ssml()
+    }
+}
+
+/// Mark a cached file as just used, for the LRU eviction
+fn touch(path: &Path) {
+    if let Ok(file) = File::options().append(true).open(path) {
+        _ = file.set_modified(SystemTime::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::workspace::DEFAULT_TEMP_DIR;
+
+    /// Writes a short silent WAV for every text, and counts the synthesised sentences
+    struct CountingTTS {
+        voice: &'static str,
+        synthesized: AtomicUsize,
+        /// left out, like a provider dropping a sentence
+        skip: Option<&'static str>,
+    }
+
+    impl CountingTTS {
+        fn new(voice: &'static str) -> Self {
+            Self {
+                voice,
+                synthesized: AtomicUsize::new(0),
+                skip: None,
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TTSService for CountingTTS {
+        async fn tts(
+            &self,
+            text_list: &Vec<String>,
+            workspace: &Workspace,
+        ) -> Result<Vec<TTSFile>, TTSError> {
+            let mut files = vec![];
+            for text in text_list {
+                if self.skip == Some(text.as_str()) {
+                    continue;
+                }
+                self.synthesized.fetch_add(1, Ordering::SeqCst);
+
+                let path = workspace.unique_path("voice.wav");
+                let spec = hound::WavSpec {
+                    channels: 1,
+                    sample_rate: 8000,
+                    bits_per_sample: 16,
+                    sample_format: hound::SampleFormat::Int,
+                };
+                let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+                // 0.1s per character, so every sentence has its own duration
+                for _ in 0..text.chars().count() * 800 {
+                    writer.write_sample(0i16).unwrap();
+                }
+                writer.finalize().unwrap();
+
+                files.push(TTSFile {
+                    duration: get_wav_len(&path).await?,
+                    path,
+                    text: text.clone(),
+                    words: None,
+                });
+            }
+            Ok(files)
+        }
+
+        fn fingerprint(&self) -> Option<String> {
+            Some(format!("counting:{}", self.voice))
+        }
+    }
+
+    fn cache_config(workspace: &Workspace, max_size_mb: u64) -> TTSCacheConfig {
+        TTSCacheConfig {
+            enabled: true,
+            dir: workspace.path("cache"),
+            max_size_mb,
+        }
+    }
+
+    fn texts(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn hit_returns_same_audio() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts = CachedTTS::new(CountingTTS::new("a"), &cache_config(&workspace, 10));
+
+        let first = tts
+            .tts(&texts(&["一二", "一二三"]), &workspace)
+            .await
+            .unwrap();
+        // one new sentence between two cached ones
+        let second = tts
+            .tts(&texts(&["一二", "四", "一二三"]), &workspace)
+            .await
+            .unwrap();
+
+        assert_eq!(tts.inner.synthesized.load(Ordering::SeqCst), 3);
+
+        let texts: Vec<&str> = second.iter().map(|f| f.text.as_str()).collect();
+        assert_eq!(texts, ["一二", "四", "一二三"]);
+        assert_eq!(second[0].duration, first[0].duration);
+        assert_eq!(second[1].duration, Duration::from_millis(100));
+        assert_eq!(second[2].duration, first[1].duration);
+        assert_ne!(second[0].path, first[0].path);
+    }
+
+    #[tokio::test]
+    async fn skipped_sentence_fails() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts = CachedTTS::new(
+            CountingTTS {
+                skip: Some("四"),
+                ..CountingTTS::new("a")
+            },
+            &cache_config(&workspace, 10),
+        );
+
+        tts.tts(&texts(&["一二"]), &workspace).await.unwrap();
+        let result = tts.tts(&texts(&["一二", "四", "五"]), &workspace).await;
+
+        // not the hit and one clip for three sentences
+        assert!(matches!(result, Err(TTSError::HandleFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn fingerprint_separates_voices() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let config = cache_config(&workspace, 10);
+
+        let a = CachedTTS::new(CountingTTS::new("a"), &config);
+        let b = CachedTTS::new(CountingTTS::new("b"), &config);
+        a.tts(&texts(&["你好"]), &workspace).await.unwrap();
+        b.tts(&texts(&["你好"]), &workspace).await.unwrap();
+
+        assert_eq!(b.inner.synthesized.load(Ordering::SeqCst), 1);
+        assert_ne!(
+            CachedTTS::<CountingTTS>::key("counting:a", "你好"),
+            CachedTTS::<CountingTTS>::key("counting:b", "你好")
+        );
+    }
+
+    #[tokio::test]
+    async fn evict_least_recently_used() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let mut tts = CachedTTS::new(CountingTTS::new("a"), &cache_config(&workspace, 10));
+
+        tts.tts(&texts(&["旧"]), &workspace).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        tts.tts(&texts(&["新"]), &workspace).await.unwrap();
+
+        // room for one file only, each is 1600 bytes of samples plus the header
+        tts.max_bytes = 2000;
+        tts.evict().await.unwrap();
+
+        let key = |text| CachedTTS::<CountingTTS>::key("counting:a", text);
+        assert!(!tts.cache_path(&key("旧")).exists());
+        assert!(tts.cache_path(&key("新")).exists());
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use crate::{
+    news::{Sentence, SpeakerRole},
+    tts::{TTSError, TTSFile, TTSService},
+    workspace::Workspace,
+};
+
+type BoxedTTS = Box<dyn TTSService + Send + Sync>;
+
+/// A voice for every speaker role, roles without their own voice are read by the anchor.
+///
+/// The sentences of a voice are synthesised in one call, so the limits and the cache
+/// of that voice still apply, and the files are returned in the sentence order.
+pub struct CastTTS {
+    anchor: BoxedTTS,
+    roles: HashMap<SpeakerRole, BoxedTTS>,
+}
+
+impl CastTTS {
+    pub fn new(anchor: impl TTSService + Send + Sync + 'static) -> Self {
+        Self {
+            anchor: Box::new(anchor),
+            roles: HashMap::new(),
+        }
+    }
+
+    pub fn with_role(
+        mut self,
+        role: SpeakerRole,
+        tts: impl TTSService + Send + Sync + 'static,
+    ) -> Self {
+        self.roles.insert(role, Box::new(tts));
+        self
+    }
+
+    /// The role whose voice reads `role`, `None` for the anchor
+    fn voice_of(&self, role: SpeakerRole) -> Option<SpeakerRole> {
+        (role != SpeakerRole::Anchor && self.roles.contains_key(&role)).then_some(role)
+    }
+}
+
+#[async_trait::async_trait]
+impl TTSService for CastTTS {
+    async fn tts(
+        &self,
+        text_list: &Vec<String>,
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        self.anchor.tts(text_list, workspace).await
+    }
+
+    async fn tts_sentences(
+        &self,
+        sentences: &[Sentence],
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        // the sentence indexes of every voice, in order of appearance
+        let mut groups: Vec<(Option<SpeakerRole>, Vec<usize>)> = vec![];
+        for (i, sentence) in sentences.iter().enumerate() {
+            let voice = self.voice_of(sentence.role);
+            match groups.iter_mut().find(|(v, _)| *v == voice) {
+                Some((_, indexes)) => indexes.push(i),
+                None => groups.push((voice, vec![i])),
+            }
+        }
+
+        let mut slots: Vec<Option<TTSFile>> = sentences.iter().map(|_| None).collect();
+        for (voice, indexes) in groups {
+            let tts = match voice {
+                Some(role) => &self.roles[&role],
+                None => &self.anchor,
+            };
+            let text_list = indexes.iter().map(|&i| sentences[i].text.clone()).collect();
+
+            let files = tts.tts(&text_list, workspace).await?;
+            if files.len() != indexes.len() {
+                return Err(TTSError::HandleFailed(format!(
+                    "expect {} clips of {}, got {}",
+                    indexes.len(),
+                    voice.map_or("anchor".to_owned(), |role| format!("{role:?}")),
+                    files.len()
+                )));
+            }
+
+            for (i, file) in indexes.into_iter().zip(files) {
+                slots[i] = Some(file);
+            }
+        }
+
+        Ok(slots.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{tts::synthetic::SyntheticTTS, workspace::DEFAULT_TEMP_DIR};
+
+    #[tokio::test]
+    async fn voice_per_role() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts = CastTTS::new(SyntheticTTS::new().with_ms_per_char(100))
+            .with_role(SpeakerRole::Male, SyntheticTTS::new().with_ms_per_char(300));
+
+        let files = tts
+            .tts_sentences(
+                &[
+                    Sentence::new("记者问"),
+                    Sentence::new("我很好").with_role(SpeakerRole::Male),
+                    Sentence::new("她说").with_role(SpeakerRole::Female),
+                    Sentence::new("他又说").with_role(SpeakerRole::Male),
+                ],
+                &workspace,
+            )
+            .await
+            .unwrap();
+
+        let texts: Vec<&str> = files.iter().map(|f| f.text.as_str()).collect();
+        assert_eq!(texts, ["记者问", "我很好", "她说", "他又说"]);
+        // female has no voice of its own, the anchor reads it
+        let durations: Vec<u64> = files
+            .iter()
+            .map(|f| f.duration.as_millis() as u64)
+            .collect();
+        assert_eq!(durations, [300, 900, 200, 900]);
+        assert_ne!(files[0].path, files[1].path);
+        assert!(
+            files
+                .iter()
+                .all(|f| f.path.exists() && f.duration > Duration::ZERO)
+        );
+    }
+}
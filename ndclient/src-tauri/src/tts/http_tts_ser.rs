@@ -1,130 +1,272 @@
-use std::{path::PathBuf, time::Duration};
+use std::time::Duration;
 
-use hound::WavReader;
-use reqwest::Client;
-use tokio::fs;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use reqwest::{Client, header};
+use serde::{Deserialize, Serialize};
 
-use crate::tts::{TTSError, TTSFile, TTSService};
-
-const DEFAULT_TEMP_DIR: &str = "./temp";
+use crate::{
+    retry::{Failure, RetryPolicy},
+    tts::{TTSError, TTSFile, TTSService, WordTiming, get_wav_len},
+    workspace::Workspace,
+};
 
+/// Client of our self-hosted `tts_service`.
+///
+/// It asks for `application/json`, so the service returns one clip per sentence
+/// instead of a single combined WAV, see `tts_service/Readme.md`.
 pub struct HttpTTSSer {
+    http: Client,
     url: String,
-    temp_dir: &'static str,
+    retry: RetryPolicy,
+}
+
+/// Request body of `POST /tts`
+#[derive(Debug, Serialize)]
+struct TtsRequest<'a> {
+    texts: &'a [&'a String],
+}
+
+/// Response of `POST /tts` with `Accept: application/json`
+#[derive(Debug, Deserialize)]
+struct TtsResponse {
+    clips: Vec<Clip>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Clip {
+    text: String,
+    /// base64 encoded WAV
+    audio: String,
+    /// optional, only engines with timestamps return it
+    #[serde(default)]
+    words: Option<Vec<ClipWord>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClipWord {
+    text: String,
+    start_ms: u64,
+    end_ms: u64,
+}
+
+impl From<ClipWord> for WordTiming {
+    fn from(word: ClipWord) -> Self {
+        Self {
+            text: word.text,
+            start: Duration::from_millis(word.start_ms),
+            end: Duration::from_millis(word.end_ms),
+        }
+    }
 }
 
 impl HttpTTSSer {
     pub fn new(url: String) -> Self {
         Self {
+            http: Client::new(),
             url,
-            temp_dir: DEFAULT_TEMP_DIR,
+            retry: RetryPolicy::default(),
         }
     }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Share a `Client`, so its connection pool is reused by every job
+    pub fn with_client(mut self, http: Client) -> Self {
+        self.http = http;
+        self
+    }
 }
 
 #[async_trait::async_trait]
 impl TTSService for HttpTTSSer {
-    async fn tts(&self, text_list: &Vec<String>) -> Result<Vec<TTSFile>, TTSError> {
+    async fn tts(
+        &self,
+        text_list: &Vec<String>,
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
         let text_list: Vec<&String> = text_list
-            .into_iter()
-            .filter(|item| !item.is_empty())
+            .iter()
+            .filter(|item| !item.trim().is_empty())
             .collect();
 
-        if text_list.len() == 0 {
+        if text_list.is_empty() {
             return Err(TTSError::EmptyText);
         }
 
-        let http = Client::new();
-        let response = http
-            .post(&self.url)
-            .json(&text_list)
-            .header("Content-Type", "application/json")
-            .send()
+        let body = TtsRequest { texts: &text_list };
+        let response: TtsResponse = self
+            .retry
+            .run(|| async {
+                let response = self
+                    .http
+                    .post(&self.url)
+                    .header(header::ACCEPT, "application/json")
+                    .json(&body)
+                    .send()
+                    .await?;
+
+                let status = response.status();
+                if !status.is_success() {
+                    let data = response.text().await.unwrap_or_default();
+                    return Err(Failure::from_status(status, &data));
+                }
+
+                response
+                    .json()
+                    .await
+                    .map_err(|e| Failure::Permanent(e.to_string()))
+            })
             .await
             .map_err(|e| TTSError::HandleFailed(e.to_string()))?;
 
-        if !response.status().is_success() {
+        if response.clips.len() != text_list.len() {
             return Err(TTSError::HandleFailed(format!(
-                "Server returned error: {}",
-                response.status()
+                "expect {} clips, server returned {}",
+                text_list.len(),
+                response.clips.len()
             )));
         }
 
-        let dir = PathBuf::from(self.temp_dir);
+        let mut list = Vec::with_capacity(response.clips.len());
+        for (i, clip) in response.clips.into_iter().enumerate() {
+            let bytes = STANDARD
+                .decode(&clip.audio)
+                .map_err(|e| TTSError::HandleFailed(format!("clip {i} is not base64: {e}")))?;
 
-        if !dir.exists() {
-            fs::create_dir(&dir)
-                .await
-                .map_err(|e| TTSError::HandleFailed(e.to_string()))?;
-        }
-
-        let path = dir.join(format!("{}.wav", nanoid::nanoid!(10)));
-
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|e| TTSError::HandleFailed(e.to_string()))?;
+            let path = workspace.unique_path(&format!("voice_{:03}.wav", i + 1));
+            tokio::fs::write(&path, bytes).await?;
 
-        tokio::fs::write(&path, bytes)
-            .await
-            .map_err(|e| TTSError::HandleFailed(e.to_string()))?;
+            // measured from the WAV itself, `duration_ms` is for clients which do not decode it
+            let duration = get_wav_len(&path).await?;
 
-        let reader = WavReader::open(&path).map_err(|e| TTSError::HandleFailed(e.to_string()))?;
+            list.push(TTSFile {
+                path,
+                text: clip.text,
+                duration,
+                words: clip
+                    .words
+                    .map(|words| words.into_iter().map(WordTiming::from).collect()),
+            });
+        }
 
-        let seconds = reader.duration() as f64 / reader.spec().sample_rate as f64;
-        let duration = Duration::from_secs_f64(seconds);
+        Ok(list)
+    }
 
-        Ok(vec![TTSFile {
-            path,
-            duration,
-            text: String::new(),
-        }])
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("http:{}", self.url))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
 
     use super::*;
+    use crate::workspace::DEFAULT_TEMP_DIR;
 
-    // #[tokio::test]
-    // async fn get_wav_file() {
-    //     let wav_data = vec![
-    //         0x52, 0x49, 0x46, 0x46, 0x24, 0x00, 0x00, 0x00, 0x57, 0x41, 0x56, 0x45, 0x66, 0x6D,
-    //         0x74, 0x20, 0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x40, 0x1F, 0x00, 0x00,
-    //         0x40, 0x1F, 0x00, 0x00, 0x01, 0x00, 0x08, 0x00, 0x64, 0x61, 0x74, 0x61, 0x00, 0x00,
-    //         0x00, 0x00,
-    //     ];
+    /// A silent WAV of `millis`
+    fn silent_wav(millis: u32) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
 
-    //     let mut server = mockito::Server::new_async().await;
+        let mut buf = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut buf, spec).unwrap();
+        for _ in 0..millis * 8 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
 
-    //     let url = server.url();
+        buf.into_inner()
+    }
 
-    //     // Create a mock
-    //     let _mock = server
-    //         .mock("POST", "/tts")
-    //         .with_status(200)
-    //         .with_header("content-type", "audio/wav")
-    //         .with_body(wav_data)
-    //         .create();
+    #[tokio::test]
+    async fn get_clip_per_sentence() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
 
-    //     let service = HttpTTSSer::new(format!("{}/tts", url));
-    //     let tts_file = service
-    //         .tts(&vec!["第一".to_owned(), "第二".to_owned()])
-    //         .await;
+        let body = serde_json::json!({
+            "clips": [
+                { "index": 0, "text": "第一", "audio": STANDARD.encode(silent_wav(500)), "duration_ms": 500 },
+                {
+                    "index": 1, "text": "第二句", "audio": STANDARD.encode(silent_wav(750)), "duration_ms": 750,
+                    "words": [
+                        { "text": "第", "start_ms": 0, "end_ms": 250 },
+                        { "text": "二", "start_ms": 250, "end_ms": 500 },
+                        { "text": "句", "start_ms": 500, "end_ms": 750 }
+                    ]
+                },
+            ]
+        });
+        let mock = server
+            .mock("POST", "/tts")
+            .match_header(header::ACCEPT, "application/json")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "texts": ["第一", "第二句"]
+            })))
+            .with_status(200)
+            .with_header(header::CONTENT_TYPE, "application/json")
+            .with_body(body.to_string())
+            .create();
 
-    //     assert!(tts_file.is_ok());
-    //     let tts_file = tts_file.unwrap();
+        let service = HttpTTSSer::new(format!("{url}/tts"));
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts_files = service
+            .tts(
+                &vec!["第一".to_owned(), "".to_owned(), "第二句".to_owned()],
+                &workspace,
+            )
+            .await
+            .unwrap();
 
-    //     assert!(tts_file.path.exists());
+        mock.assert();
+        assert_eq!(tts_files.len(), 2);
+        assert_eq!(tts_files[0].text, "第一");
+        assert_eq!(tts_files[0].duration, Duration::from_millis(500));
+        assert_eq!(tts_files[1].text, "第二句");
+        assert_eq!(tts_files[1].duration, Duration::from_millis(750));
+        assert!(tts_files[0].words.is_none());
+        let words = tts_files[1].words.as_ref().unwrap();
+        assert_eq!(words[1].text, "二");
+        assert_eq!(words[1].start, Duration::from_millis(250));
+        assert!(tts_files[1].path.exists());
+    }
 
-    //     fs::remove_file(tts_file.path).await.unwrap();
-    // }
+    #[tokio::test]
+    async fn clip_count_mismatch() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let body = serde_json::json!({
+            "clips": [{ "index": 0, "text": "第一", "audio": STANDARD.encode(silent_wav(500)), "duration_ms": 500 }]
+        });
+        let _mock = server
+            .mock("POST", "/tts")
+            .with_status(200)
+            .with_header(header::CONTENT_TYPE, "application/json")
+            .with_body(body.to_string())
+            .create();
+
+        let service = HttpTTSSer::new(format!("{url}/tts"));
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let result = service
+            .tts(&vec!["第一".to_owned(), "第二".to_owned()], &workspace)
+            .await;
+
+        assert!(matches!(result, Err(TTSError::HandleFailed(_))));
+    }
 
     #[tokio::test]
     async fn empty_text_list() {
         let service = HttpTTSSer::new("".to_owned());
-        let tts_file = service.tts(&vec!["".to_owned()]).await;
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts_file = service.tts(&vec!["".to_owned()], &workspace).await;
 
         assert!(tts_file.is_err());
     }
@@ -0,0 +1,224 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use futures::{StreamExt, TryStreamExt, stream};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::tts::{TTSError, TTSFile};
+
+/// Lowest limit of the requests per second, one request every 100 s
+pub const MIN_REQUESTS_PER_SECOND: f32 = 0.01;
+
+/// How hard a TTS service may be hit while synthesising one video
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SynthesisLimits {
+    /// sentences synthesised at the same time
+    pub max_concurrent: usize,
+    /// requests started per second, `0` means no limit
+    pub requests_per_second: f32,
+}
+
+impl Default for SynthesisLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            requests_per_second: 5.0,
+        }
+    }
+}
+
+impl SynthesisLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_concurrent == 0 {
+            return Err("max_concurrent must be greater than 0".to_owned());
+        }
+        let rps = self.requests_per_second;
+        if !rps.is_finite() || (rps != 0.0 && rps < MIN_REQUESTS_PER_SECOND) {
+            return Err(format!(
+                "requests_per_second must be 0 or at least {MIN_REQUESTS_PER_SECOND}, got {rps}"
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Spaces requests evenly, so no more than `requests_per_second` are started in any second
+pub struct RateLimiter {
+    interval: Option<Duration>,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// A limit below [`MIN_REQUESTS_PER_SECOND`] is raised to it
+    pub fn new(requests_per_second: f32) -> Self {
+        let interval = (requests_per_second > 0.0).then(|| {
+            Duration::from_secs_f32(1.0 / requests_per_second.max(MIN_REQUESTS_PER_SECOND))
+        });
+
+        Self {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait until the next request is allowed to start
+    pub async fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+
+        let start = {
+            let mut next = self.next.lock().await;
+            let start = (*next).max(Instant::now());
+            *next = start + interval;
+            start
+        };
+
+        tokio::time::sleep_until(start.into()).await;
+    }
+}
+
+/// Run the synthesis `tasks`, at most `max_concurrent` at once.
+///
+/// The files keep the order of `tasks` whichever request finishes first,
+/// the first error cancels the requests still running.
+pub async fn synthesize_ordered<Fut>(
+    tasks: Vec<Fut>,
+    limits: &SynthesisLimits,
+    limiter: &RateLimiter,
+) -> Result<Vec<TTSFile>, TTSError>
+where
+    Fut: Future<Output = Result<TTSFile, TTSError>>,
+{
+    stream::iter(tasks)
+        .map(|task| async move {
+            limiter.wait().await;
+            task.await
+        })
+        .buffered(limits.max_concurrent.max(1))
+        .try_collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn texts(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("第{i}句")).collect()
+    }
+
+    #[tokio::test]
+    async fn keep_order_and_bound_concurrency() {
+        let limits = SynthesisLimits {
+            max_concurrent: 3,
+            requests_per_second: 0.0,
+        };
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let text_list = texts(8);
+
+        let tasks = text_list
+            .iter()
+            .enumerate()
+            .map(|(index, text)| {
+                let (running, peak) = (&running, &peak);
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    // later sentences finish first
+                    tokio::time::sleep(Duration::from_millis(10 * (8 - index as u64))).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+
+                    Ok(TTSFile {
+                        path: format!("{index}.wav").into(),
+                        text: text.clone(),
+                        duration: Duration::ZERO,
+                        words: None,
+                    })
+                }
+            })
+            .collect();
+
+        let files = synthesize_ordered(tasks, &limits, &RateLimiter::new(0.0))
+            .await
+            .unwrap();
+
+        let result: Vec<String> = files.into_iter().map(|f| f.text).collect();
+        assert_eq!(result, text_list);
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn first_error_fails_all() {
+        let tasks = (0..4)
+            .map(|index| async move {
+                if index == 2 {
+                    Err(TTSError::Network("boom".to_owned()))
+                } else {
+                    Ok(TTSFile {
+                        path: format!("{index}.wav").into(),
+                        text: String::new(),
+                        duration: Duration::ZERO,
+                        words: None,
+                    })
+                }
+            })
+            .collect();
+
+        let result =
+            synthesize_ordered(tasks, &SynthesisLimits::default(), &RateLimiter::new(0.0)).await;
+
+        assert!(matches!(result, Err(TTSError::Network(_))));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_spaces_requests() {
+        let limiter = RateLimiter::new(20.0);
+        let start = Instant::now();
+
+        for _ in 0..5 {
+            limiter.wait().await;
+        }
+
+        // the first request starts at once, the other 4 wait 50ms each
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn validate_limits() {
+        assert!(SynthesisLimits::default().validate().is_ok());
+
+        let limits = SynthesisLimits {
+            max_concurrent: 0,
+            ..Default::default()
+        };
+        assert!(limits.validate().is_err());
+
+        let limits = SynthesisLimits {
+            requests_per_second: -1.0,
+            ..Default::default()
+        };
+        assert!(limits.validate().is_err());
+
+        let limits = SynthesisLimits {
+            requests_per_second: 1e-40,
+            ..Default::default()
+        };
+        assert!(limits.validate().is_err());
+    }
+
+    #[test]
+    fn tiny_rate_does_not_overflow() {
+        let limiter = RateLimiter::new(f32::MIN_POSITIVE / 2.0);
+
+        assert_eq!(limiter.interval, Some(Duration::from_secs(100)));
+    }
+}
@@ -0,0 +1,284 @@
+use std::{io, path::PathBuf, process::Stdio};
+
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{
+    tts::{
+        TTSError, TTSFile, TTSService, get_wav_len,
+        limiter::{RateLimiter, SynthesisLimits, synthesize_ordered},
+    },
+    workspace::Workspace,
+};
+
+/// Command line of a local TTS engine.
+///
+/// `{output}` in `args` is replaced by the WAV path to write, `{text}` by the sentence
+/// and `{voice}` by the voice.
+/// Without `{text}`, the sentence is written to stdin instead, as `piper` expects.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalTTSConfig {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// voice of the anchor, the other speaker roles can have their own
+    pub voice: String,
+    /// the engine reads SSML, e.g. espeak-ng with `-m`
+    pub ssml: bool,
+}
+
+impl Default for LocalTTSConfig {
+    fn default() -> Self {
+        Self {
+            program: PathBuf::from("espeak-ng"),
+            args: ["-v", "{voice}", "-w", "{output}", "{text}"]
+                .map(String::from)
+                .to_vec(),
+            voice: "cmn".to_owned(),
+            ssml: false,
+        }
+    }
+}
+
+impl LocalTTSConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.program.as_os_str().is_empty() {
+            return Err("program cannot be empty".to_owned());
+        }
+        if !self.args.iter().any(|arg| arg.contains("{output}")) {
+            return Err("args must contain {output}".to_owned());
+        }
+
+        Ok(())
+    }
+
+    fn reads_stdin(&self) -> bool {
+        !self.args.iter().any(|arg| arg.contains("{text}"))
+    }
+}
+
+/// TTS by a CPU engine running as a subprocess, e.g. espeak-ng or piper.
+/// No network is needed, for development and CI.
+pub struct LocalTTS {
+    config: LocalTTSConfig,
+    limits: SynthesisLimits,
+    limiter: RateLimiter,
+}
+
+impl LocalTTS {
+    pub fn new(config: LocalTTSConfig) -> Self {
+        Self {
+            config,
+            limits: SynthesisLimits::default(),
+            limiter: RateLimiter::new(0.0),
+        }
+    }
+
+    /// Speak with another voice than the configured one
+    pub fn with_voice(mut self, voice: impl Into<String>) -> Self {
+        self.config.voice = voice.into();
+        self
+    }
+
+    /// Only `max_concurrent` is used, a local engine has no rate limit
+    pub fn with_limits(mut self, limits: SynthesisLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    async fn synthesize(
+        &self,
+        index: usize,
+        text: &str,
+        workspace: &Workspace,
+    ) -> Result<TTSFile, TTSError> {
+        let path = workspace.unique_path(&format!("voice_{:03}.wav", index + 1));
+        let output = path.to_string_lossy();
+
+        let args = self.config.args.iter().map(|arg| {
+            arg.replace("{output}", &output)
+                .replace("{voice}", &self.config.voice)
+                .replace("{text}", text)
+        });
+
+        let reads_stdin = self.config.reads_stdin();
+        let mut child = Command::new(&self.config.program)
+            .args(args)
+            .stdin(if reads_stdin {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                TTSError::HandleFailed(format!("cannot run {}: {e}", self.config.program.display()))
+            })?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // an engine failing early closes stdin, its exit status tells why
+            if let Err(e) = stdin.write_all(text.as_bytes()).await
+                && e.kind() != io::ErrorKind::BrokenPipe
+            {
+                return Err(e.into());
+            }
+            // closing stdin ends the input
+            drop(stdin);
+        }
+
+        let result = child.wait_with_output().await?;
+        if !result.status.success() {
+            return Err(TTSError::HandleFailed(format!(
+                "{} exited with {}: {}",
+                self.config.program.display(),
+                result.status,
+                String::from_utf8_lossy(&result.stderr).trim()
+            )));
+        }
+
+        let duration = get_wav_len(&path).await?;
+
+        Ok(TTSFile {
+            path,
+            text: text.to_owned(),
+            duration,
+            words: None,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl TTSService for LocalTTS {
+    async fn tts(
+        &self,
+        text_list: &Vec<String>,
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        if text_list.iter().all(|text| text.trim().is_empty()) {
+            return Err(TTSError::EmptyText);
+        }
+
+        let tasks = text_list
+            .iter()
+            .enumerate()
+            .map(|(index, text)| self.synthesize(index, text, workspace))
+            .collect();
+
+        synthesize_ordered(tasks, &self.limits, &self.limiter).await
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!(
+            "local:{}:{}:{}",
+            self.config.program.display(),
+            self.config.args.join(" "),
+            self.config.voice
+        ))
+    }
+
+    fn accepts_ssml(&self) -> bool {
+        self.config.ssml
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::workspace::DEFAULT_TEMP_DIR;
+
+    fn mock_voice() -> String {
+        std::fs::canonicalize("./tests/mock_voice.wav")
+            .unwrap()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn sh(script: String, args: &[&str]) -> LocalTTSConfig {
+        let mut all = vec!["-c".to_owned(), script];
+        all.extend(args.iter().map(|a| a.to_string()));
+
+        LocalTTSConfig {
+            program: PathBuf::from("sh"),
+            args: all,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn text_as_argument() {
+        // fails unless the sentence arrives untouched
+        let config = sh(
+            format!(r#"test "$1" = '他说"你好"' && cp {} "$0""#, mock_voice()),
+            &["{output}", "{text}"],
+        );
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+
+        let files = LocalTTS::new(config)
+            .tts(&vec![r#"他说"你好""#.to_owned()], &workspace)
+            .await
+            .unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].text, r#"他说"你好""#);
+        assert!(files[0].duration > std::time::Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn voice_as_argument() {
+        let config = sh(
+            format!(r#"test "$1" = "en" && cp {} "$0""#, mock_voice()),
+            &["{output}", "{voice}"],
+        );
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+
+        let result = LocalTTS::new(config)
+            .with_voice("en")
+            .tts(&vec!["hello".to_owned()], &workspace)
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn text_from_stdin() {
+        let config = sh(
+            format!(r#"test "$(cat)" = "第二句" && cp {} "$0""#, mock_voice()),
+            &["{output}"],
+        );
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+
+        let result = LocalTTS::new(config)
+            .tts(&vec!["第二句".to_owned()], &workspace)
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn engine_failed() {
+        let config = sh("echo no voice >&2; exit 3".to_owned(), &["{output}"]);
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+
+        let result = LocalTTS::new(config)
+            .tts(&vec!["测试".to_owned()], &workspace)
+            .await;
+
+        let Err(TTSError::HandleFailed(reason)) = result else {
+            panic!("expect HandleFailed");
+        };
+        assert!(reason.contains("no voice"));
+    }
+
+    #[test]
+    fn validate_config() {
+        assert!(LocalTTSConfig::default().validate().is_ok());
+
+        let config = LocalTTSConfig {
+            args: vec!["{text}".to_owned()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
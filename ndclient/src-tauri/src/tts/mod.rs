@@ -1,13 +1,77 @@
 pub mod ali_tts;
+pub mod align;
+pub mod cache;
+pub mod cast;
 pub mod http_tts_ser;
+pub mod limiter;
+pub mod local_tts;
+pub mod normalize;
+pub mod synthetic;
 
 use hound::WavReader;
 use std::{io, path::PathBuf, time::Duration};
 use thiserror::Error;
 
+use crate::{news::Sentence, workspace::Workspace};
+
 #[async_trait::async_trait]
 pub trait TTSService {
-    async fn tts(&self, text_list: &Vec<String>) -> Result<Vec<TTSFile>, TTSError>;
+    /// Synthesise every text into its own audio file inside `workspace`
+    async fn tts(
+        &self,
+        text_list: &Vec<String>,
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError>;
+
+    /// Synthesise sentences of different speakers, see [`cast::CastTTS`].
+    /// All of them are spoken by the same voice by default.
+    async fn tts_sentences(
+        &self,
+        sentences: &[Sentence],
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        let text_list = sentences.iter().map(|s| s.text.clone()).collect();
+        self.tts(&text_list, workspace).await
+    }
+
+    /// Identifies everything but the text that changes the audio,
+    /// e.g. provider, model, voice and speaking parameters.
+    /// `None` means the audio cannot be cached.
+    fn fingerprint(&self) -> Option<String> {
+        None
+    }
+
+    /// The texts may be SSML instead of plain text, see [`normalize`]
+    fn accepts_ssml(&self) -> bool {
+        false
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: TTSService + Send + Sync + ?Sized> TTSService for Box<T> {
+    async fn tts(
+        &self,
+        text_list: &Vec<String>,
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        (**self).tts(text_list, workspace).await
+    }
+
+    async fn tts_sentences(
+        &self,
+        sentences: &[Sentence],
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        (**self).tts_sentences(sentences, workspace).await
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        (**self).fingerprint()
+    }
+
+    fn accepts_ssml(&self) -> bool {
+        (**self).accepts_ssml()
+    }
 }
 
 pub struct TTSFile {
@@ -17,6 +81,17 @@ pub struct TTSFile {
     pub text: String,
     // this audio duartion
     pub duration: Duration,
+    /// timing of every word (character for Chinese) from the clip start,
+    /// `None` if the provider does not tell, see [`align`] for the fallback
+    pub words: Option<Vec<WordTiming>>,
+}
+
+/// When a word is spoken inside its clip
+#[derive(Clone, Debug, PartialEq)]
+pub struct WordTiming {
+    pub text: String,
+    pub start: Duration,
+    pub end: Duration,
 }
 
 #[derive(Error, Debug)]
@@ -0,0 +1,566 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tts::{TTSError, TTSFile, TTSService},
+    workspace::Workspace,
+};
+
+const DIGITS: [char; 10] = ['零', '一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
+/// Numbers longer than this are read digit by digit, like IDs and phone numbers
+const MAX_CARDINAL_DIGITS: usize = 12;
+
+/// Config of the text normalisation before TTS
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalizeConfig {
+    pub enabled: bool,
+    /// pronunciation lexicon TOML file, empty for none
+    pub lexicon: PathBuf,
+}
+
+impl Default for NormalizeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            lexicon: PathBuf::new(),
+        }
+    }
+}
+
+/// How a word should be pronounced
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pronunciation {
+    /// text spoken instead of the word, e.g. `"A I"` for `"AI"`
+    pub say: Option<String>,
+    /// tone-numbered pinyin, only used by SSML providers, e.g. `"chong2 qing4"`
+    pub pinyin: Option<String>,
+}
+
+/// User editable pronunciation dictionary, stored as TOML:
+///
+/// ```toml
+/// [words]
+/// "AI" = { say = "A I" }
+/// "重庆" = { pinyin = "chong2 qing4" }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Lexicon {
+    pub words: BTreeMap<String, Pronunciation>,
+}
+
+impl Lexicon {
+    /// Load the lexicon file, a missing file is an empty lexicon
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TTSError> {
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)
+                .map_err(|e| TTSError::HandleFailed(format!("invalid lexicon: {e}"))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The longest word at the start of `text`
+    fn longest_match(&self, text: &str) -> Option<(&str, &Pronunciation)> {
+        self.words
+            .iter()
+            .filter(|(word, _)| !word.is_empty() && text.starts_with(word.as_str()))
+            .max_by_key(|(word, _)| word.len())
+            .map(|(word, p)| (word.as_str(), p))
+    }
+}
+
+/// Turns the written text into what should be spoken:
+/// numbers, dates, times and percentages are read out in Chinese,
+/// and the lexicon fixes the words the TTS mispronounces.
+#[derive(Clone, Debug, Default)]
+pub struct Normalizer {
+    lexicon: Lexicon,
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Word(&'a str, &'a Pronunciation),
+}
+
+impl Normalizer {
+    pub fn new(lexicon: Lexicon) -> Self {
+        Self { lexicon }
+    }
+
+    /// Plain text for providers without SSML
+    pub fn spoken(&self, text: &str) -> String {
+        self.segments(text)
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => read_numbers(text),
+                Segment::Word(word, p) => p.say.clone().unwrap_or_else(|| word.to_owned()),
+            })
+            .collect()
+    }
+
+    /// SSML for providers accepting it, the pinyin of the lexicon becomes `<phoneme>`
+    pub fn ssml(&self, text: &str) -> String {
+        let body: String = self
+            .segments(text)
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => escape_xml(&read_numbers(text)),
+                Segment::Word(word, p) => match (&p.pinyin, &p.say) {
+                    (Some(pinyin), _) => format!(
+                        r#"<phoneme alphabet="py" ph="{}">{}</phoneme>"#,
+                        escape_xml(pinyin),
+                        escape_xml(word)
+                    ),
+                    (None, Some(say)) => {
+                        format!(
+                            r#"<sub alias="{}">{}</sub>"#,
+                            escape_xml(say),
+                            escape_xml(word)
+                        )
+                    }
+                    (None, None) => escape_xml(word),
+                },
+            })
+            .collect();
+
+        format!("<speak>{body}</speak>")
+    }
+
+    /// Split `text` into the lexicon words and the text between them
+    fn segments<'a>(&'a self, text: &'a str) -> Vec<Segment<'a>> {
+        let mut segments = vec![];
+        let mut plain_start = 0;
+        let mut i = 0;
+
+        while i < text.len() {
+            if let Some((word, p)) = self.lexicon.longest_match(&text[i..]) {
+                if plain_start < i {
+                    segments.push(Segment::Text(&text[plain_start..i]));
+                }
+                segments.push(Segment::Word(word, p));
+                i += word.len();
+                plain_start = i;
+            } else {
+                i += text[i..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        if plain_start < text.len() {
+            segments.push(Segment::Text(&text[plain_start..]));
+        }
+
+        segments
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Read every number of `text` in Chinese
+pub fn read_numbers(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut i = 0;
+
+    while i < chars.len() {
+        if !chars[i].is_ascii_digit() {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        // a minus sign, not a hyphen after latin words or numbers
+        if out.ends_with('-') {
+            let before = out[..out.len() - 1].chars().next_back();
+            if before.is_none_or(|c| !c.is_ascii_alphanumeric()) {
+                out.pop();
+                out.push('负');
+            }
+        }
+
+        let (consumed, spoken) = read_number_at(&chars[i..]);
+        out.push_str(&spoken);
+        i += consumed;
+    }
+
+    out
+}
+
+/// Read the number at the start of `chars`, with the context after it
+fn read_number_at(chars: &[char]) -> (usize, String) {
+    let digits_at = |from: usize| {
+        chars[from.min(chars.len())..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count()
+    };
+
+    let int_len = digits_at(0);
+    let int: String = chars[..int_len].iter().collect();
+    let next = chars.get(int_len).copied();
+
+    // dates: 2025-07-03, 2025/7/3
+    if int_len == 4 && matches!(next, Some('-' | '/')) {
+        let month_len = digits_at(int_len + 1);
+        let sep = chars.get(int_len + 1 + month_len).copied();
+        if (1..=2).contains(&month_len) && sep == next {
+            let day_from = int_len + 2 + month_len;
+            let day_len = digits_at(day_from);
+            if (1..=2).contains(&day_len) {
+                let month: String = chars[int_len + 1..int_len + 1 + month_len].iter().collect();
+                let day: String = chars[day_from..day_from + day_len].iter().collect();
+                let spoken = format!(
+                    "{}年{}月{}日",
+                    read_digits(&int),
+                    read_integer(month.parse().unwrap_or(0)),
+                    read_integer(day.parse().unwrap_or(0))
+                );
+                return (day_from + day_len, spoken);
+            }
+        }
+    }
+
+    // a year is read digit by digit
+    if next == Some('年') && (2..=4).contains(&int_len) {
+        return (int_len, read_digits(&int));
+    }
+
+    // times: 9:05, 14:30
+    if int_len <= 2 && next == Some(':') {
+        let minute_len = digits_at(int_len + 1);
+        if minute_len == 2 {
+            let minute: String = chars[int_len + 1..int_len + 3].iter().collect();
+            let minute = minute.parse::<u64>().unwrap_or(0);
+            let spoken = match minute {
+                0 => format!("{}点", read_cardinal(&int)),
+                1..=9 => format!("{}点零{}分", read_cardinal(&int), read_integer(minute)),
+                _ => format!("{}点{}分", read_cardinal(&int), read_integer(minute)),
+            };
+            return (int_len + 3, spoken);
+        }
+    }
+
+    // thousands separators: 1,234,567
+    let mut len = int_len;
+    let mut int = int;
+    if int_len <= 3 {
+        while chars.get(len) == Some(&',') && digits_at(len + 1) == 3 && digits_at(len + 4) == 0 {
+            int.extend(&chars[len + 1..len + 4]);
+            len += 4;
+        }
+    }
+
+    let mut spoken = read_cardinal(&int);
+
+    // decimals: 3.14
+    if chars.get(len) == Some(&'.') {
+        let frac_len = digits_at(len + 1);
+        if frac_len > 0 {
+            let frac: String = chars[len + 1..len + 1 + frac_len].iter().collect();
+            spoken = format!("{spoken}点{}", read_digits(&frac));
+            len += 1 + frac_len;
+        }
+    }
+
+    // percentages: 12.5%
+    if matches!(chars.get(len), Some('%' | '％')) {
+        return (len + 1, format!("百分之{spoken}"));
+    }
+
+    (len, spoken)
+}
+
+/// Read digit by digit, e.g. years and codes
+fn read_digits(digits: &str) -> String {
+    digits
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|d| DIGITS[d as usize])
+        .collect()
+}
+
+/// Read as a number, unless it looks like a code: a leading zero or too many digits
+fn read_cardinal(digits: &str) -> String {
+    if (digits.len() > 1 && digits.starts_with('0')) || digits.len() > MAX_CARDINAL_DIGITS {
+        return read_digits(digits);
+    }
+
+    digits
+        .parse::<u64>()
+        .map(read_integer)
+        .unwrap_or_else(|_| read_digits(digits))
+}
+
+/// Chinese reading of an integer, e.g. 10020 is 一万零二十
+pub fn read_integer(n: u64) -> String {
+    if n == 0 {
+        return DIGITS[0].to_string();
+    }
+
+    const UNITS: [&str; 4] = ["", "万", "亿", "万亿"];
+
+    let mut sections = vec![];
+    let mut rest = n;
+    while rest > 0 {
+        sections.push(rest % 10_000);
+        rest /= 10_000;
+    }
+
+    let mut out = String::new();
+    let mut zero_before = false;
+    for (i, &section) in sections.iter().enumerate().rev() {
+        if section == 0 {
+            zero_before = !out.is_empty();
+            continue;
+        }
+        if !out.is_empty() && (zero_before || section < 1000) {
+            out.push(DIGITS[0]);
+        }
+        out.push_str(&read_section(section));
+        out.push_str(UNITS[i]);
+        zero_before = false;
+    }
+
+    // 十五, not 一十五
+    if out.starts_with("一十") {
+        out.remove(0);
+    }
+
+    out
+}
+
+/// Reading of 1 ~ 9999
+fn read_section(n: u64) -> String {
+    const UNITS: [&str; 4] = ["千", "百", "十", ""];
+
+    let digits = [n / 1000, n / 100 % 10, n / 10 % 10, n % 10];
+    let mut out = String::new();
+    let mut zero = false;
+
+    for (digit, unit) in digits.into_iter().zip(UNITS) {
+        if digit == 0 {
+            zero = !out.is_empty();
+            continue;
+        }
+        if zero {
+            out.push(DIGITS[0]);
+            zero = false;
+        }
+        out.push(DIGITS[digit as usize]);
+        out.push_str(unit);
+    }
+
+    out
+}
+
+/// Normalises the text before `inner` synthesises it.
+///
+/// The returned files carry the original text for the subtitles, blank sentences
+/// have no file like with the providers. When the spoken text differs, the word timings of the provider no longer
+/// match the written words, so they are dropped for the alignment fallback.
+pub struct NormalizedTTS<T> {
+    inner: T,
+    normalizer: Normalizer,
+}
+
+impl<T: TTSService + Send + Sync> NormalizedTTS<T> {
+    pub fn new(inner: T, normalizer: Normalizer) -> Self {
+        Self { inner, normalizer }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: TTSService + Send + Sync> TTSService for NormalizedTTS<T> {
+    async fn tts(
+        &self,
+        text_list: &Vec<String>,
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        // SSML of a blank sentence is not blank, they are left out before
+        let originals: Vec<&String> = text_list
+            .iter()
+            .filter(|text| !text.trim().is_empty())
+            .collect();
+        if originals.is_empty() {
+            return Err(TTSError::EmptyText);
+        }
+
+        let ssml = self.inner.accepts_ssml();
+        let spoken: Vec<String> = originals
+            .iter()
+            .map(|text| {
+                if ssml {
+                    self.normalizer.ssml(text)
+                } else {
+                    self.normalizer.spoken(text)
+                }
+            })
+            .collect();
+
+        let mut files = self.inner.tts(&spoken, workspace).await?;
+
+        // the files cannot be matched to the sentences, the subtitles would show the spoken text
+        if files.len() != originals.len() {
+            return Err(TTSError::HandleFailed(format!(
+                "{} clips synthesised for {} sentences",
+                files.len(),
+                originals.len()
+            )));
+        }
+
+        for (file, original) in files.iter_mut().zip(originals) {
+            if file.text != *original {
+                file.text = original.clone();
+                file.words = None;
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        self.inner.fingerprint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tts::synthetic::SyntheticTTS,
+        workspace::{DEFAULT_TEMP_DIR, Workspace},
+    };
+
+    #[test]
+    fn integers() {
+        assert_eq!(read_integer(0), "零");
+        assert_eq!(read_integer(15), "十五");
+        assert_eq!(read_integer(105), "一百零五");
+        assert_eq!(read_integer(1020), "一千零二十");
+        assert_eq!(read_integer(10020), "一万零二十");
+        assert_eq!(read_integer(100_000), "十万");
+        assert_eq!(read_integer(120_000_003), "一亿二千万零三");
+        assert_eq!(read_integer(3_0000_1000), "三亿零一千");
+    }
+
+    #[test]
+    fn numbers_in_text() {
+        assert_eq!(read_numbers("上涨3.14%"), "上涨百分之三点一四");
+        assert_eq!(
+            read_numbers("共1,234,567人"),
+            "共一百二十三万四千五百六十七人"
+        );
+        assert_eq!(read_numbers("气温-5度"), "气温负五度");
+        assert_eq!(read_numbers("K-9"), "K-九");
+        assert_eq!(read_numbers("验证码0451"), "验证码零四五一");
+        assert_eq!(read_numbers("第3名"), "第三名");
+    }
+
+    #[test]
+    fn dates_and_times() {
+        assert_eq!(read_numbers("2025年7月3日"), "二零二五年七月三日");
+        assert_eq!(read_numbers("于2025-07-03发布"), "于二零二五年七月三日发布");
+        assert_eq!(read_numbers("14:30开始"), "十四点三十分开始");
+        assert_eq!(read_numbers("9:05"), "九点零五分");
+    }
+
+    fn lexicon() -> Lexicon {
+        toml::from_str(
+            r#"
+[words]
+"AI" = { say = "A I" }
+"重庆" = { pinyin = "chong2 qing4" }
+"5G" = { say = "五G" }
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn plain_with_lexicon() {
+        let normalizer = Normalizer::new(lexicon());
+
+        assert_eq!(
+            normalizer.spoken("重庆发布5G与AI计划，投资20亿"),
+            "重庆发布五G与A I计划，投资二十亿"
+        );
+    }
+
+    #[test]
+    fn ssml_with_lexicon() {
+        let normalizer = Normalizer::new(lexicon());
+
+        assert_eq!(
+            normalizer.ssml("重庆<AI>&3人"),
+            r#"<speak><phoneme alphabet="py" ph="chong2 qing4">重庆</phoneme>&lt;<sub alias="A I">AI</sub>&gt;&amp;三人</speak>"#
+        );
+    }
+
+    #[test]
+    fn missing_lexicon_file() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let lexicon = Lexicon::load(workspace.path("lexicon.toml")).unwrap();
+
+        assert!(lexicon.words.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keep_original_text() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts = NormalizedTTS::new(SyntheticTTS::new(), Normalizer::default());
+
+        let files = tts
+            .tts(&vec!["涨5%".to_owned(), "不变".to_owned()], &workspace)
+            .await
+            .unwrap();
+
+        assert_eq!(files[0].text, "涨5%");
+        // spoken as 涨百分之五
+        assert_eq!(
+            files[0].duration,
+            SyntheticTTS::new().duration_of("涨百分之五")
+        );
+        assert!(files[0].words.is_none());
+        assert!(files[1].words.is_some());
+    }
+
+    #[tokio::test]
+    async fn skip_blank_sentences() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts = NormalizedTTS::new(SyntheticTTS::new(), Normalizer::default());
+
+        let files = tts
+            .tts(
+                &vec!["涨5%".to_owned(), " ".to_owned(), "不变".to_owned()],
+                &workspace,
+            )
+            .await
+            .unwrap();
+
+        let texts: Vec<&str> = files.iter().map(|f| f.text.as_str()).collect();
+        assert_eq!(texts, ["涨5%", "不变"]);
+
+        let result = tts.tts(&vec![String::new()], &workspace).await;
+        assert!(matches!(result, Err(TTSError::EmptyText)));
+    }
+}
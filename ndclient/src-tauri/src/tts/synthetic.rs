@@ -0,0 +1,162 @@
+use std::{f32::consts::PI, time::Duration};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::{
+    tts::{TTSError, TTSFile, TTSService, align::align_even},
+    workspace::Workspace,
+};
+
+pub const DEFAULT_MS_PER_CHAR: u32 = 200;
+pub const SAMPLE_RATE: u32 = 16_000;
+
+/// Fake TTS for tests, no engine or network is needed.
+///
+/// Every sentence becomes a mono 16 kHz WAV of exactly `ms_per_char` per character,
+/// silent or a sine tone, so the timing of the whole pipeline can be asserted exactly.
+pub struct SyntheticTTS {
+    ms_per_char: u32,
+    /// frequency of the tone, silence if `None`
+    tone_hz: Option<f32>,
+}
+
+impl SyntheticTTS {
+    pub fn new() -> Self {
+        Self {
+            ms_per_char: DEFAULT_MS_PER_CHAR,
+            tone_hz: None,
+        }
+    }
+
+    pub fn with_ms_per_char(mut self, ms_per_char: u32) -> Self {
+        self.ms_per_char = ms_per_char;
+        self
+    }
+
+    /// A sine tone instead of silence, for steps which skip silence
+    pub fn with_tone(mut self, hz: f32) -> Self {
+        self.tone_hz = Some(hz);
+        self
+    }
+
+    /// The exact duration of the audio of `text`
+    pub fn duration_of(&self, text: &str) -> Duration {
+        Duration::from_millis(text.chars().count() as u64 * self.ms_per_char as u64)
+    }
+
+    fn write_wav(&self, text: &str, file: &std::path::Path) -> Result<(), TTSError> {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let to_err = |e: hound::Error| TTSError::HandleFailed(e.to_string());
+
+        let samples = self.duration_of(text).as_millis() as u32 * SAMPLE_RATE / 1000;
+
+        let mut writer = WavWriter::create(file, spec).map_err(to_err)?;
+        for i in 0..samples {
+            let sample = match self.tone_hz {
+                Some(hz) => {
+                    let t = i as f32 / SAMPLE_RATE as f32;
+                    ((2.0 * PI * hz * t).sin() * i16::MAX as f32 * 0.3) as i16
+                }
+                None => 0,
+            };
+            writer.write_sample(sample).map_err(to_err)?;
+        }
+        writer.finalize().map_err(to_err)?;
+
+        Ok(())
+    }
+}
+
+impl Default for SyntheticTTS {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl TTSService for SyntheticTTS {
+    async fn tts(
+        &self,
+        text_list: &Vec<String>,
+        workspace: &Workspace,
+    ) -> Result<Vec<TTSFile>, TTSError> {
+        if text_list.iter().all(|text| text.trim().is_empty()) {
+            return Err(TTSError::EmptyText);
+        }
+
+        let mut list = Vec::with_capacity(text_list.len());
+        for (i, text) in text_list.iter().enumerate() {
+            let path = workspace.unique_path(&format!("voice_{:03}.wav", i + 1));
+            self.write_wav(text, &path)?;
+
+            // every character takes the same time, so the timings are exact
+            let duration = self.duration_of(text);
+            list.push(TTSFile {
+                path,
+                text: text.clone(),
+                duration,
+                words: Some(align_even(text, duration)),
+            });
+        }
+
+        Ok(list)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!(
+            "synthetic:{}ms:{}",
+            self.ms_per_char,
+            self.tone_hz.map(|hz| hz.to_string()).unwrap_or_default()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tts::get_wav_len, workspace::DEFAULT_TEMP_DIR};
+
+    #[tokio::test]
+    async fn duration_by_char_count() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts = SyntheticTTS::new().with_ms_per_char(150);
+
+        let files = tts
+            .tts(&vec!["第五人格".to_owned(), "启动!".to_owned()], &workspace)
+            .await
+            .unwrap();
+
+        assert_eq!(files[0].duration, Duration::from_millis(600));
+        assert_eq!(files[1].duration, Duration::from_millis(450));
+        for file in &files {
+            assert_eq!(get_wav_len(&file.path).await.unwrap(), file.duration);
+        }
+    }
+
+    #[tokio::test]
+    async fn tone_is_deterministic() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let tts = SyntheticTTS::new().with_tone(440.0);
+
+        let first = tts.tts(&vec!["你好".to_owned()], &workspace).await.unwrap();
+        let first_path = first[0].path.clone();
+        let first = std::fs::read(&first_path).unwrap();
+        let second = tts.tts(&vec!["你好".to_owned()], &workspace).await.unwrap();
+        let second = std::fs::read(&second[0].path).unwrap();
+
+        assert_eq!(first, second);
+
+        let mut reader = hound::WavReader::open(&first_path).unwrap();
+        let peak = reader
+            .samples::<i16>()
+            .map(|s| s.unwrap().unsigned_abs())
+            .max()
+            .unwrap();
+        assert!(peak > 1000);
+    }
+}
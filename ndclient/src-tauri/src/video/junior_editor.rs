@@ -4,45 +4,80 @@ use tokio::fs;
 
 use crate::{
     news::NewsMaterial,
+    retry::{Failure, RetryPolicy},
     // resize_img::resize_to_9_16,
-    video::{VideoEditor, VideoEditorError, VideoEditorResult},
+    timeline::Timeline,
+    video::{VideoEditor, VideoEditorError, VideoEditorResult, VideoFormat, ffmpeg_error},
+    workspace::Workspace,
 };
 
-const DEFAULT_TEMP_DIR: &str = "./temp";
-
 pub struct JuniorEditor {
-    temp_dir: &'static str,
     http: Client,
+    format: VideoFormat,
+    retry: RetryPolicy,
 }
 
 impl JuniorEditor {
     pub fn new() -> Self {
         Self {
-            temp_dir: DEFAULT_TEMP_DIR,
             http: Client::new(),
+            format: VideoFormat::default(),
+            retry: RetryPolicy::default(),
         }
     }
+
+    pub fn with_format(mut self, format: VideoFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Share a `Client`, so its connection pool is reused by every job
+    pub fn with_client(mut self, http: Client) -> Self {
+        self.http = http;
+        self
+    }
 }
 
 #[async_trait::async_trait]
 impl VideoEditor for JuniorEditor {
-    async fn do_edit(&self, material: &NewsMaterial, dur: Duration) -> VideoEditorResult<PathBuf> {
-        let pic_urls = Self::get_need_pics(&material.pics, dur);
-        let pic_files = self.save_pics(pic_urls).await?;
+    async fn do_edit(
+        &self,
+        material: &NewsMaterial,
+        timeline: &Timeline,
+        workspace: &Workspace,
+    ) -> VideoEditorResult<PathBuf> {
+        let pic_urls = self.get_need_pics(&material.pics, timeline.duration);
+        let pic_files = self.save_pics(pic_urls, workspace).await?;
         // let resized_pics = self.resize_pics(&pic_files).await?;
 
-        let path = self.compose_pics(&pic_files).await?.canonicalize()?;
+        let path = self
+            .compose_pics(&pic_files, workspace)
+            .await?
+            .canonicalize()?;
 
         Ok(path)
     }
+
+    fn transitions(&self, timeline: &Timeline) -> Vec<Duration> {
+        let step = Duration::from_secs(self.format.seconds_per_pic.max(1) as u64);
+
+        (1..)
+            .map(|i| step * i)
+            .take_while(|at| *at < timeline.duration)
+            .collect()
+    }
 }
 
 impl JuniorEditor {
-    fn get_need_pics(pics: &Vec<String>, dur: Duration) -> Vec<String> {
-        let mut pic_need = (dur.as_secs() / 2).max(1) as usize;
-        if dur.subsec_millis() > 0 {
-            pic_need += 1;
-        }
+    fn get_need_pics(&self, pics: &Vec<String>, dur: Duration) -> Vec<String> {
+        // enough to cover the whole dubbing, which `-shortest` would cut otherwise
+        let millis_per_pic = self.format.seconds_per_pic.max(1) as u128 * 1000;
+        let pic_need = dur.as_millis().div_ceil(millis_per_pic).max(1) as usize;
 
         let pic_count = pics.len();
 
@@ -67,48 +102,23 @@ impl JuniorEditor {
         pics
     }
 
-    async fn save_pics(&self, pics: Vec<String>) -> VideoEditorResult<Vec<PathBuf>> {
+    async fn save_pics(
+        &self,
+        pics: Vec<String>,
+        workspace: &Workspace,
+    ) -> VideoEditorResult<Vec<PathBuf>> {
         let id = nanoid::nanoid!(10);
 
         let mut pic_files = vec![];
 
-        let path = PathBuf::from(format!("{}", self.temp_dir));
-        fs::create_dir_all(&path).await?;
-        let path = path.canonicalize().unwrap();
-
         for (i, pic_url) in pics.iter().enumerate() {
-            let response = self
-                .http
-                .get(pic_url)
-                .send()
-                .await
-                .map_err(|e| VideoEditorError::NetWork(e.to_string()))?;
-
-            let format = {
-                let content_type = response
-                    .headers()
-                    .get("content-type")
-                    .ok_or(VideoEditorError::NetWork(
-                        "cannot find image format".to_owned(),
-                    ))?
-                    .to_str()
-                    .unwrap();
-
-                content_type
-                    .split('/')
-                    .nth(1)
-                    .ok_or(VideoEditorError::NetWork(
-                        "malformed content-type".to_owned(),
-                    ))?
-                    .to_string()
-            };
-
-            let bytes = response
-                .bytes()
+            let (format, bytes) = self
+                .retry
+                .run(|| self.download_pic(pic_url))
                 .await
-                .map_err(|e| VideoEditorError::NetWork(e.to_string()))?;
+                .map_err(|e| VideoEditorError::NetWork(format!("download {pic_url}: {e}")))?;
 
-            let file_path = path.join(format!("{}{:03}.{}", id, i, format));
+            let file_path = workspace.path(&format!("{}{:03}.{}", id, i, format));
             fs::write(&file_path, bytes).await?;
 
             pic_files.push(file_path);
@@ -117,6 +127,34 @@ impl JuniorEditor {
         Ok(pic_files)
     }
 
+    /// Download a picture, and its format from the content type
+    async fn download_pic(&self, pic_url: &str) -> Result<(String, Vec<u8>), Failure> {
+        let response = self.http.get(pic_url).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Failure::from_status(status, ""));
+        }
+
+        let format = {
+            let content_type = response
+                .headers()
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+                .ok_or(Failure::Permanent("cannot find image format".to_owned()))?;
+
+            content_type
+                .split('/')
+                .nth(1)
+                .ok_or(Failure::Permanent("malformed content-type".to_owned()))?
+                .to_string()
+        };
+
+        let bytes = response.bytes().await?;
+
+        Ok((format, bytes.to_vec()))
+    }
+
     // async fn resize_pics(&self, pics: &Vec<PathBuf>) -> VideoEditorResult<Vec<PathBuf>> {
     //     let mut resized_pics = vec![];
 
@@ -136,18 +174,22 @@ impl JuniorEditor {
     //     Ok(resized_pics)
     // }
 
-    async fn compose_pics(&self, pics: &Vec<PathBuf>) -> VideoEditorResult<PathBuf> {
+    async fn compose_pics(
+        &self,
+        pics: &Vec<PathBuf>,
+        workspace: &Workspace,
+    ) -> VideoEditorResult<PathBuf> {
         if pics.is_empty() {
             return Err(VideoEditorError::Image("no pics to compose".to_owned()));
         }
 
-        let output_path = PathBuf::from(format!(
-            "{}/{}-composed.mp4",
-            self.temp_dir,
-            nanoid::nanoid!(10)
-        ));
+        let output_path = workspace.unique_path("composed.mp4");
+
+        let file_list_path = self.build_file_list(pics, workspace).await?;
 
-        let file_list_path = self.build_file_list(pics).await?;
+        let VideoFormat {
+            width, height, fps, ..
+        } = self.format;
 
         let mut cmd = tokio::process::Command::new("ffmpeg");
         cmd.arg("-y")
@@ -156,34 +198,37 @@ impl JuniorEditor {
             .arg("-i")
             .arg(&file_list_path)
             .arg("-vf")
-            .arg("scale=720:1280:force_original_aspect_ratio=decrease,pad=720:1280:(ow-iw)/2:(oh-ih)/2:color=black")
+            .arg(format!("scale={width}:{height}:force_original_aspect_ratio=decrease,pad={width}:{height}:(ow-iw)/2:(oh-ih)/2:color=black"))
             .arg("-c:v")
             .arg("libx264")
             .arg("-r")
-            .arg("30")
+            .arg(fps.to_string())
             .arg("-pix_fmt")
             .arg("yuv420p")
             .arg(output_path.to_str().unwrap());
 
-        cmd.output().await?;
-        _ = fs::remove_file(file_list_path).await;
-
-        for pic in pics {
-            _ = fs::remove_file(pic).await;
+        let output = cmd.output().await?;
+        if !output.status.success() {
+            return Err(VideoEditorError::Ffmpeg(format!(
+                "slideshow failed with {}: {}",
+                output.status,
+                ffmpeg_error(&output.stderr)
+            )));
         }
+
         Ok(output_path)
     }
 
-    async fn build_file_list(&self, pics: &Vec<PathBuf>) -> VideoEditorResult<PathBuf> {
+    async fn build_file_list(
+        &self,
+        pics: &Vec<PathBuf>,
+        workspace: &Workspace,
+    ) -> VideoEditorResult<PathBuf> {
         if pics.is_empty() {
             return Err(VideoEditorError::Image("no pics to compose".to_owned()));
         }
 
-        let file_list_path = PathBuf::from(format!(
-            "{}/{}-file-list.txt",
-            self.temp_dir,
-            nanoid::nanoid!(10)
-        ));
+        let file_list_path = workspace.unique_path("file-list.txt");
 
         let mut file_list_content = String::new();
         for pic in pics {
@@ -191,7 +236,10 @@ impl JuniorEditor {
                 "file '{}'\n",
                 pic.file_name().unwrap().to_str().unwrap()
             ));
-            file_list_content.push_str(&format!("duration 2\n\n"));
+            file_list_content.push_str(&format!(
+                "duration {}\n\n",
+                self.format.seconds_per_pic.max(1)
+            ));
         }
 
         file_list_content.push_str(&format!(
@@ -211,6 +259,7 @@ mod tests {
     use std::time::Duration;
 
     use super::*;
+    use crate::workspace::DEFAULT_TEMP_DIR;
 
     #[tokio::test]
     async fn edit_video_need_three() {
@@ -221,7 +270,7 @@ mod tests {
             videos: vec![],
         };
 
-        let res = JuniorEditor::get_need_pics(&material.pics, Duration::from_millis(5300));
+        let res = JuniorEditor::new().get_need_pics(&material.pics, Duration::from_millis(5300));
         assert_eq!(res.len(), 3);
 
         let expected_pics = vec!["pic1".to_owned(), "pic2".to_owned(), "pic1".to_owned()];
@@ -238,7 +287,7 @@ mod tests {
             videos: vec![],
         };
 
-        let res = JuniorEditor::get_need_pics(&material.pics, Duration::from_millis(4000));
+        let res = JuniorEditor::new().get_need_pics(&material.pics, Duration::from_millis(4000));
         assert_eq!(2, res.len());
 
         let expected_pics = vec!["pic1".to_owned(), "pic2".to_owned()];
@@ -255,7 +304,7 @@ mod tests {
             videos: vec![],
         };
 
-        let res = JuniorEditor::get_need_pics(&material.pics, Duration::from_millis(1000));
+        let res = JuniorEditor::new().get_need_pics(&material.pics, Duration::from_millis(1000));
         assert_eq!(1, res.len());
 
         let expected_pics = vec!["pic1".to_owned()];
@@ -263,6 +312,45 @@ mod tests {
         assert_eq!(expected_pics, res);
     }
 
+    #[test]
+    fn cover_whole_seconds() {
+        let pics = vec!["pic1".to_owned(), "pic2".to_owned()];
+
+        // 61s at 2s per picture
+        let res = JuniorEditor::new().get_need_pics(&pics, Duration::from_secs(61));
+        assert_eq!(res.len(), 31);
+
+        let editor = JuniorEditor::new().with_format(VideoFormat {
+            seconds_per_pic: 7,
+            ..VideoFormat::default()
+        });
+        assert_eq!(
+            editor.get_need_pics(&pics, Duration::from_secs(60)).len(),
+            9
+        );
+
+        let editor = JuniorEditor::new().with_format(VideoFormat {
+            seconds_per_pic: 0,
+            ..VideoFormat::default()
+        });
+        assert_eq!(editor.get_need_pics(&pics, Duration::from_secs(3)).len(), 3);
+    }
+
+    #[test]
+    fn transitions_every_pic() {
+        let editor = JuniorEditor::new();
+
+        assert_eq!(
+            editor.transitions(&Timeline::silent(Duration::from_millis(6500))),
+            [2, 4, 6].map(Duration::from_secs)
+        );
+        assert!(
+            editor
+                .transitions(&Timeline::silent(Duration::from_secs(2)))
+                .is_empty()
+        );
+    }
+
     #[tokio::test]
     async fn edit_video_need_many() {
         let material = NewsMaterial {
@@ -280,7 +368,7 @@ mod tests {
             videos: vec![],
         };
 
-        let res = JuniorEditor::get_need_pics(&material.pics, Duration::from_millis(8_300));
+        let res = JuniorEditor::new().get_need_pics(&material.pics, Duration::from_millis(8_300));
         assert_eq!(5, res.len());
 
         let expected_pics = vec![
@@ -311,18 +399,15 @@ mod tests {
             .create();
 
         let editor = JuniorEditor::new();
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
 
         let pics = vec![format!("{url}/images"), format!("{url}/images")];
 
-        let paths = editor.save_pics(pics).await;
+        let paths = editor.save_pics(pics, &workspace).await;
         assert!(paths.is_ok());
 
         let paths = paths.unwrap();
 
-        for path in &paths {
-            _ = fs::remove_file(path).await;
-        }
-
         assert_eq!(2, paths.len());
     }
 
@@ -351,18 +436,15 @@ mod tests {
             .create();
 
         let editor = JuniorEditor::new();
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
 
         let pics = vec![format!("{url}/image_png"), format!("{url}/image_jpg")];
 
-        let paths = editor.save_pics(pics).await;
+        let paths = editor.save_pics(pics, &workspace).await;
         assert!(paths.is_ok());
 
         let paths = paths.unwrap();
 
-        for path in &paths {
-            _ = fs::remove_file(path).await;
-        }
-
         assert_eq!(2, paths.len());
     }
 
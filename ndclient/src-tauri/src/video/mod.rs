@@ -1,9 +1,32 @@
-use crate::news::NewsMaterial;
+use crate::{news::NewsMaterial, timeline::Timeline, workspace::Workspace};
+use serde::{Deserialize, Serialize};
 use std::{io, path::PathBuf, time::Duration};
 use thiserror::Error;
 
 pub mod junior_editor;
 
+/// Format of the edited video
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoFormat {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// how long every picture is shown
+    pub seconds_per_pic: u32,
+}
+
+impl Default for VideoFormat {
+    fn default() -> Self {
+        Self {
+            width: 720,
+            height: 1280,
+            fps: 30,
+            seconds_per_pic: 2,
+        }
+    }
+}
+
 pub type VideoEditorResult<T> = Result<T, VideoEditorError>;
 
 #[derive(Error, Debug)]
@@ -14,14 +37,35 @@ pub enum VideoEditorError {
     IO(#[from] io::Error),
     #[error("Image error: {0}")]
     Image(String),
+    #[error("ffmpeg error: {0}")]
+    Ffmpeg(String),
+}
+
+/// The last lines of the stderr of ffmpeg, the banner comes first and the error last
+pub(crate) fn ffmpeg_error(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let tail: Vec<&str> = stderr.trim_end().lines().rev().take(5).collect();
+
+    tail.into_iter().rev().collect::<Vec<_>>().join("\n")
 }
 
 #[async_trait::async_trait]
 pub trait VideoEditor {
     /// Edit the video Pics and return the path of the edited video.
-    /// The `dur` is the duration of the video, which is used to determine how many pictures are needed.
+    /// The duration of `timeline` is the one of the video, which is used to determine how many pictures are needed.
     /// The `material` contains the information of the news material, including the pictures.
     /// The edited video will be in 9:16 aspect ratio.
     /// The Video without subtitle, dubbing
-    async fn do_edit(&self, material: &NewsMaterial, dur: Duration) -> VideoEditorResult<PathBuf>;
+    /// All intermediate files and the edited video are written into `workspace`.
+    async fn do_edit(
+        &self,
+        material: &NewsMaterial,
+        timeline: &Timeline,
+        workspace: &Workspace,
+    ) -> VideoEditorResult<PathBuf>;
+
+    /// When the pictures change along `timeline`, none by default
+    fn transitions(&self, _timeline: &Timeline) -> Vec<Duration> {
+        vec![]
+    }
 }
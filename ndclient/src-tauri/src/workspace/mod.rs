@@ -0,0 +1,116 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const DEFAULT_TEMP_DIR: &str = "./temp";
+
+/// Scoped directory of a single job.
+///
+/// Every component writes its intermediate files through the paths handed out here,
+/// so concurrent jobs never share a file. The whole directory is removed on drop,
+/// unless `keep_artifacts` is set for debugging.
+#[derive(Debug)]
+pub struct Workspace {
+    id: String,
+    root: PathBuf,
+    keep_artifacts: bool,
+}
+
+impl Workspace {
+    /// Create a new unique job directory under `base`
+    pub fn create(base: impl AsRef<Path>) -> io::Result<Self> {
+        let id = nanoid::nanoid!(10);
+        let root = base.as_ref().join(&id);
+        fs::create_dir_all(&root)?;
+        let root = root.canonicalize()?;
+
+        Ok(Self {
+            id,
+            root,
+            keep_artifacts: false,
+        })
+    }
+
+    /// Keep the job directory after drop, useful to inspect intermediate files
+    pub fn keep_artifacts(mut self, keep: bool) -> Self {
+        self.keep_artifacts = keep;
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of `name` inside the job directory
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    /// A unique path inside the job directory, like `<root>/<nanoid>-<suffix>`
+    pub fn unique_path(&self, suffix: &str) -> PathBuf {
+        self.root
+            .join(format!("{}-{}", nanoid::nanoid!(10), suffix))
+    }
+
+    /// Create (if needed) and return a sub directory of the job directory
+    pub fn sub_dir(&self, name: &str) -> io::Result<PathBuf> {
+        let dir = self.root.join(name);
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if self.keep_artifacts {
+            return;
+        }
+
+        _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_on_drop() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let root = workspace.root().to_path_buf();
+
+        fs::write(workspace.path("a.txt"), "a").unwrap();
+        workspace.sub_dir("sub").unwrap();
+
+        assert!(root.exists());
+        drop(workspace);
+        assert!(!root.exists());
+    }
+
+    #[test]
+    fn keep_artifacts_on_drop() {
+        let workspace = Workspace::create(DEFAULT_TEMP_DIR)
+            .unwrap()
+            .keep_artifacts(true);
+        let root = workspace.root().to_path_buf();
+
+        drop(workspace);
+        assert!(root.exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn unique_between_jobs() {
+        let first = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+        let second = Workspace::create(DEFAULT_TEMP_DIR).unwrap();
+
+        assert_ne!(first.root(), second.root());
+        assert_ne!(first.unique_path("a.wav"), first.unique_path("a.wav"));
+    }
+}
@@ -1,11 +1,11 @@
-use core::panic;
-use std::time::Duration;
-
 use ndclient_lib::{
     director::{Director, source::NewsSource},
-    news::{MaterialExtractor, NewsCrawler, NewsMaterial, NewsMaterialResult, NewsTitle},
+    news::{
+        MaterialExtractor, NewsCrawler, NewsMaterial, NewsMaterialResult, NewsTitle, Sentence,
+        SpeakerRole,
+    },
     subtitle::srt::SrtSubtitle,
-    tts::{TTSError, TTSFile, TTSService},
+    tts::{cast::CastTTS, synthetic::SyntheticTTS},
     video::junior_editor::JuniorEditor,
 };
 use tokio::fs;
@@ -36,9 +36,9 @@ impl MaterialExtractor for MockNewsMaterialExtractor {
         Ok(NewsMaterial {
             title: hot_news.title.clone(),
             summary: vec![
-                "鸡块狗".to_owned(),
-                "闺蜜闺蜜要不要跟我玩第五人格喵喵喵".to_owned(),
-                "兄弟兄弟要不要跟我玩第五人格喵喵喵".to_owned(),
+                Sentence::new("鸡块狗"),
+                Sentence::new("闺蜜闺蜜要不要跟我玩第五人格喵喵喵").with_role(SpeakerRole::Female),
+                Sentence::new("兄弟兄弟要不要跟我玩第五人格喵喵喵").with_role(SpeakerRole::Male),
             ],
                 pics: vec![
                     "https://plus.unsplash.com/premium_photo-1675337267945-3b2fff5344a0?fm=jpg&q=60&w=300".to_owned(),
@@ -49,46 +49,19 @@ impl MaterialExtractor for MockNewsMaterialExtractor {
     }
 }
 
-struct MockTTSSer;
-
-#[async_trait::async_trait]
-impl TTSService for MockTTSSer {
-    async fn tts(&self, _text_list: &Vec<String>) -> Result<Vec<TTSFile>, TTSError> {
-        let temp_dir = fs::canonicalize("./tests").await.unwrap();
-
-        let mock_file = temp_dir.join("mock_voice.wav");
-
-        let path = temp_dir.join("fake_tts_output.wav");
-
-        if path.exists() {
-            return Ok(vec![TTSFile {
-                path,
-                text: String::new(),
-                duration: Duration::from_secs(2),
-            }]);
-        }
-
-        fs::copy(mock_file, &path).await.unwrap();
-
-        if !path.exists() {
-            panic!("has not test wav file: {}", path.display());
-        }
-        Ok(vec![TTSFile {
-            path,
-            text: String::new(),
-            duration: Duration::from_secs(2),
-        }])
-    }
-}
-
 #[tokio::test]
 async fn director_shot_single() {
     let mut director = Director::new(NewsSource {
         crawler: Box::new(MockNewsCrawler),
         extractor: MockNewsMaterialExtractor.into(),
     })
-    .with_tts(MockTTSSer)
-    .with_subtitle(SrtSubtitle::new())
+    .with_tts(
+        CastTTS::new(SyntheticTTS::new().with_tone(440.0))
+            .with_role(SpeakerRole::Female, SyntheticTTS::new().with_tone(660.0)),
+    )
+    .with_subtitle(
+        SrtSubtitle::new().with_colors([(SpeakerRole::Female, "#FF80C0".to_owned())].into()),
+    )
     .with_video_editor(JuniorEditor::new());
 
     let list = director.get_hot_news_list().await;
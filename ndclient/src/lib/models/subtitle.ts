@@ -0,0 +1,4 @@
+export type SubtitleStyle = {
+    id: string,
+    name: string,
+};
@@ -0,0 +1,27 @@
+export type AliTTSOptions = {
+    model: string,
+    voice: string,
+    rate: number,
+    pitch: number,
+    volume: number,
+};
+
+export type AliVoice = {
+    id: string,
+    name: string,
+    gender: string,
+    description: string,
+    models: string[],
+};
+
+export type Effect =
+    | { type: 'speed', rate: number }
+    | { type: 'tempo', rate: number }
+    | { type: 'pitch', semitones: number }
+    | { type: 'eq', preset: 'radio' | 'telephone' | 'bright' | 'warm' };
+
+export type EffectPreset = {
+    id: string,
+    name: string,
+    chain: Effect[],
+};
@@ -1,3 +1,4 @@
+import base64
 import torch
 import uuid
 import os
@@ -49,6 +50,13 @@ def text_to_wav(text, file_path):
     return file_path
 
 
+def segment_to_base64(segment):
+    """将音频片段导出为 WAV 并 base64 编码"""
+    output_io = BytesIO()
+    segment.export(output_io, format="wav")
+    return base64.b64encode(output_io.getvalue()).decode('ascii')
+
+
 @app.route('/tts', methods=['POST'])
 def generate_tts():
     """
@@ -56,7 +64,16 @@ def generate_tts():
     {
         "texts": ["文本1", "文本2", ...]
     }
-    返回拼接后的 WAV 文件
+
+    默认返回拼接后的 WAV 文件。
+    请求头 `Accept: application/json` 时，逐句返回：
+    {
+        "clips": [
+            {"index": 0, "text": "文本1", "audio": "<base64 WAV>", "duration_ms": 1234},
+            ...
+        ]
+    }
+    clips 与 texts 一一对应，空白文本返回静音片段
     """
     data = request.get_json()
     texts = data.get('texts', [])
@@ -65,27 +82,42 @@ def generate_tts():
     if not isinstance(texts, list) or not all(isinstance(t, str) for t in texts):
         return jsonify({"error": "Invalid input: 'texts' should be a list of strings."}), 400
 
+    per_sentence = request.accept_mimetypes.best_match(['audio/wav', 'application/json']) == 'application/json'
+
     temp_files = []
-    combined_audio = AudioSegment.empty()
 
     try:
-        for i, text in enumerate(texts):
+        segments = []
+        for text in texts:
             if not text.strip():
+                segments.append(AudioSegment.silent(duration=0))
                 continue
             temp_file = os.path.join(TEMP_DIR, f"{uuid.uuid4()}.wav")
-            text_to_wav(text, temp_file)
-            segment = AudioSegment.from_wav(temp_file)
-            combined_audio += segment
-            if i < len(texts) - 1:
-                combined_audio += SILENCE
             temp_files.append(temp_file)
+            text_to_wav(text, temp_file)
+            segments.append(AudioSegment.from_wav(temp_file))
+
+        if per_sentence:
+            clips = [
+                {
+                    "index": i,
+                    "text": text,
+                    "audio": segment_to_base64(segment),
+                    "duration_ms": len(segment),
+                }
+                for i, (text, segment) in enumerate(zip(texts, segments))
+            ]
+            return jsonify({"clips": clips})
 
         # 导出最终合并的音频
-        # wav_name = f"{uuid.uuid4()}.wav"
+        combined_audio = AudioSegment.empty()
+        for i, segment in enumerate(segments):
+            combined_audio += segment
+            if i < len(segments) - 1:
+                combined_audio += SILENCE
 
         output_io = BytesIO()
         combined_audio.export(output_io, format="wav")
-        # combined_audio.export(f"./output_wav/{wav_name}", format="wav")
 
         return send_file(output_io, mimetype='audio/wav', as_attachment=True, download_name="combined_output.wav")
 